@@ -0,0 +1,568 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self,Write};
+
+use crate::cells::{rowbit, Expr};
+use crate::gates::{levelorder, tokenize, Circuit, FiveLogic, Gates, ParseError, ParseReason};
+
+struct Reader<'a> {
+    filename: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, token: &str, reason: ParseReason) -> ParseError {
+        self.errorat((1, token), reason)
+    }
+
+    // An error at a token of the current line and the column it starts at.
+    fn errorat(&self, (column, token): (usize, &str), reason: ParseReason) -> ParseError {
+        ParseError {
+            file: self.filename.to_string(),
+            line: self.line,
+            column,
+            token: token.to_string(),
+            reason,
+        }
+    }
+
+    fn readline(&mut self) -> Result<String, ParseError> {
+        if self.pos >= self.bytes.len() {
+            self.line += 1;
+            return Err(self.error("", ParseReason::MissingOperand));
+        }
+
+        let start = self.pos;
+        while self.pos < self.bytes.len() && self.bytes[self.pos] != b'\n' {
+            self.pos += 1;
+        }
+        let text = String::from_utf8_lossy(&self.bytes[start..self.pos]).to_string();
+        self.pos += 1;
+        self.line += 1;
+
+        Ok(text)
+    }
+
+    // Reads a line of exactly `count` numbers, or between `count` and `max`.
+    fn numbers(&mut self, count: usize, max: usize) -> Result<Vec<u32>, ParseError> {
+        let text = self.readline()?;
+        let tokens = tokenize(&text);
+
+        if tokens.len() < count {
+            return Err(self.error(&text, ParseReason::MissingOperand));
+        }
+        if tokens.len() > max {
+            return Err(self.errorat(tokens[max], ParseReason::WrongOperandCount));
+        }
+
+        let mut values = vec![];
+        for t in tokens {
+            match t.1.parse::<u32>() {
+                Ok(v) => values.push(v),
+                Err(_) => return Err(self.errorat(t, ParseReason::NonNumericNet)),
+            }
+        }
+
+        Ok(values)
+    }
+
+    fn varint(&mut self) -> Result<u32, ParseError> {
+        let mut value: u32 = 0;
+        let mut shift = 0;
+
+        loop {
+            let Some(byte) = self.bytes.get(self.pos) else {
+                return Err(self.error("", ParseReason::MissingOperand));
+            };
+            self.pos += 1;
+
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+            if shift > 28 {
+                return Err(self.error("", ParseReason::Malformed));
+            }
+        }
+    }
+}
+
+// Turns AIGER literals into nets. A positive literal is the net numbered by
+// its variable; a complemented literal is the output of an INV on that net,
+// and the constants 0 and 1 are the outputs of TIE0 and TIE1 cells, each
+// created the first time it is needed.
+struct Literals {
+    inverted: HashMap<u32,u32>,
+    ties: [Option<u32>; 2],
+    next: u32,
+}
+
+impl Literals {
+    fn net(&mut self, circuit: &mut Circuit, lit: u32) -> u32 {
+        let var = lit / 2;
+        if var != 0 && lit & 1 == 0 {
+            return var;
+        }
+
+        let known = if var == 0 { self.ties[lit as usize] } else { self.inverted.get(&var).copied() };
+        if let Some(net) = known {
+            return net;
+        }
+
+        self.next += 1;
+        let net = self.next;
+        if var == 0 {
+            circuit.addgate(if lit == 0 { "TIE0" } else { "TIE1" }, &[], net);
+            self.ties[lit as usize] = Some(net);
+        } else {
+            circuit.addgate("INV", &[var], net);
+            self.inverted.insert(var, net);
+        }
+
+        net
+    }
+}
+
+pub fn parseaiger(filename: &str) -> Result<Circuit, ParseError> {
+    match fs::read(filename) {
+        Ok(bytes) => parseaigerbytes(filename, &bytes),
+        Err(_) => Err(ParseError {
+            file: filename.to_string(),
+            line: 0,
+            column: 0,
+            token: String::new(),
+            reason: ParseReason::UnreadableFile,
+        }),
+    }
+}
+
+// Reads an And-Inverter Graph in either the ASCII (aag) or binary (aig)
+// AIGER format. AND nodes become AND gates, complemented edges become INV
+// gates and latches become flops. Constant literals become TIE0 and TIE1
+// cells.
+pub fn parseaigerbytes(filename: &str, bytes: &[u8]) -> Result<Circuit, ParseError> {
+    let mut r = Reader { filename, bytes, pos: 0, line: 0 };
+    let mut circuit = Circuit::new();
+
+    let header = r.readline()?;
+    let tokens = tokenize(&header);
+
+    let binary = match tokens.first() {
+        Some((_, "aag")) => false,
+        Some((_, "aig")) => true,
+        Some(t) => return Err(r.errorat(*t, ParseReason::UnknownGate)),
+        None => return Err(r.error("", ParseReason::Malformed)),
+    };
+
+    if tokens.len() < 6 {
+        return Err(r.error(&header, ParseReason::MissingOperand));
+    }
+    let mut counts = vec![];
+    for t in &tokens[1..] {
+        match t.1.parse::<u32>() {
+            Ok(v) => counts.push(v),
+            Err(_) => return Err(r.errorat(*t, ParseReason::NonNumericNet)),
+        }
+    }
+    // the AIGER 1.9 bad, constraint, justice and fairness sections
+    if let Some(k) = counts[5..].iter().position(|c| *c != 0) {
+        return Err(r.errorat(tokens[6 + k], ParseReason::Unsupported));
+    }
+    let (m, i, l, o, a) = (counts[0], counts[1], counts[2], counts[3], counts[4]);
+
+    let mut lits = Literals { inverted: HashMap::new(), ties: [None, None], next: m };
+
+    for k in 0..i {
+        let var = if binary {
+            k + 1
+        } else {
+            let lit = r.numbers(1, 1)?[0];
+            if lit < 2 || lit & 1 == 1 {
+                return Err(r.error(&lit.to_string(), ParseReason::Malformed));
+            }
+            lit / 2
+        };
+        circuit.addinput(var);
+    }
+
+    let mut latches = vec![];
+    for k in 0..l {
+        let values = if binary { r.numbers(1, 2)? } else { r.numbers(2, 3)? };
+        let (var, rest) = if binary { (i + k + 1, &values[..]) } else { (values[0] / 2, &values[1..]) };
+        latches.push((var, rest[0], rest.get(1).copied().unwrap_or(0)));
+    }
+
+    let mut outputs = vec![];
+    for _ in 0..o {
+        outputs.push(r.numbers(1, 1)?[0]);
+    }
+
+    let mut ands = vec![];
+    for k in 0..a {
+        if binary {
+            let lhs = 2 * (i + l + k + 1);
+            let delta0 = r.varint()?;
+            let delta1 = r.varint()?;
+            if delta0 > lhs || delta1 > lhs - delta0 {
+                return Err(r.error("", ParseReason::Malformed));
+            }
+            ands.push((lhs, lhs - delta0, lhs - delta0 - delta1, r.line + 1));
+        } else {
+            let values = r.numbers(3, 3)?;
+            ands.push((values[0], values[1], values[2], r.line));
+        }
+    }
+
+    for (lhs, rhs0, rhs1, line) in ands {
+        if lhs & 1 == 1 {
+            r.line = line;
+            return Err(r.error(&format!("{} {} {}", lhs, rhs0, rhs1), ParseReason::Unsupported));
+        }
+        let ins = [lits.net(&mut circuit, rhs0), lits.net(&mut circuit, rhs1)];
+        circuit.addgate("AND", &ins, lhs / 2);
+    }
+
+    for (var, next, init) in latches {
+        let d = lits.net(&mut circuit, next);
+        let init = match init {
+            0 => FiveLogic::ZERO,
+            1 => FiveLogic::ONE,
+            _ => FiveLogic::X,
+        };
+        circuit.addflop(d, var, init);
+    }
+
+    for lit in outputs {
+        let net = lits.net(&mut circuit, lit);
+        circuit.addoutput(net);
+    }
+
+    // the optional symbol table names inputs, latches and outputs
+    while r.pos < r.bytes.len() {
+        let line = r.readline()?;
+        if line == "c" {
+            break;
+        }
+
+        let (symbol, name) = line.split_once(' ').unwrap_or((&line, ""));
+        let index = symbol.get(1..).and_then(|k| k.parse::<usize>().ok());
+        let net = match (symbol.chars().next(), index) {
+            (Some('i'), Some(k)) => circuit.inputs.get(k).copied(),
+            (Some('l'), Some(k)) => circuit.flops.get(k).map(|f| f.q),
+            (Some('o'), Some(k)) => circuit.outputs.get(k).copied(),
+            _ => None,
+        };
+
+        match net {
+            Some(net) if !name.is_empty() => {
+                circuit.names.insert(net, name.to_string());
+            },
+            _ => return Err(r.error(symbol, ParseReason::Malformed)),
+        }
+    }
+
+    Ok(circuit)
+}
+
+fn encode<W: Write>(out: &mut W, mut value: u32) -> io::Result<()> {
+    while value & !0x7f != 0 {
+        out.write_all(&[(value & 0x7f) as u8 | 0x80])?;
+        value >>= 7;
+    }
+    out.write_all(&[value as u8])
+}
+
+type AndNode = dyn Fn(u32, u32, &mut Vec<(u32,u32,u32)>) -> u32;
+
+// Builds a cell's function out of AND nodes and returns the literal of its
+// output.
+fn cellliteral(expr: &Expr, ins: &[u32], and: &AndNode, ands: &mut Vec<(u32,u32,u32)>) -> u32 {
+    match expr {
+        Expr::Pin(k) => ins[*k],
+        Expr::Const(value) => *value as u32,
+        Expr::Not(e) => cellliteral(e, ins, and, ands) ^ 1,
+        Expr::And(terms) | Expr::Or(terms) | Expr::Xor(terms) => {
+            let lits: Vec<u32> = terms.iter().map(|t| cellliteral(t, ins, and, ands)).collect();
+
+            let mut acc = lits[0];
+            for lit in &lits[1..] {
+                acc = match expr {
+                    Expr::And(_) => and(acc, *lit, ands),
+                    Expr::Or(_) => and(acc ^ 1, *lit ^ 1, ands) ^ 1,
+                    _ => {
+                        let left = and(acc, *lit ^ 1, ands);
+                        let right = and(acc ^ 1, *lit, ands);
+                        and(left ^ 1, right ^ 1, ands) ^ 1
+                    },
+                };
+            }
+            acc
+        },
+        Expr::Table(table) => {
+            let n = ins.len();
+            let mut acc: Option<u32> = None;
+
+            // one AND chain per row of the on-set, ORed together
+            for (row, _) in table.iter().enumerate().filter(|(_, out)| **out) {
+                let mut term = if rowbit(row, 0, n) { ins[0] } else { ins[0] ^ 1 };
+                for (k, lit) in ins.iter().enumerate().skip(1) {
+                    term = and(term, if rowbit(row, k, n) { *lit } else { *lit ^ 1 }, ands);
+                }
+                acc = Some(match acc {
+                    Some(acc) => and(acc ^ 1, term ^ 1, ands) ^ 1,
+                    None => term,
+                });
+            }
+            acc.unwrap_or(0)
+        },
+    }
+}
+
+// Writes the circuit as an AIGER file, ASCII or binary. Every gate is
+// rebuilt from two input ANDs and complemented edges: OR and NOR through De
+// Morgan, XOR and XNOR as three ANDs per extra input, INV and BUF as plain
+// edges. Cells are built from their library function and flop resets as an
+// AND on the latch input. Inputs, latches and outputs are listed in the symbol
+// table by name.
+pub fn writeaiger<W: Write>(circuit: &Circuit, binary: bool, out: &mut W) -> io::Result<()> {
+    let mut lits: HashMap<u32,u32> = HashMap::new();
+    let mut ands: Vec<(u32,u32,u32)> = vec![];
+
+    let i = circuit.inputs.len() as u32;
+    let l = circuit.flops.len() as u32;
+
+    for (k, net) in circuit.inputs.iter().enumerate() {
+        lits.insert(*net, 2 * (k as u32 + 1));
+    }
+    for (k, flop) in circuit.flops.iter().enumerate() {
+        lits.insert(flop.q, 2 * (i + k as u32 + 1));
+    }
+
+    let and = move |a: u32, b: u32, ands: &mut Vec<(u32,u32,u32)>| -> u32 {
+        if a == b {
+            return a;
+        }
+        let lhs = 2 * (i + l + ands.len() as u32 + 1);
+        ands.push((lhs, a.max(b), a.min(b)));
+        lhs
+    };
+
+    let (_, order) = levelorder(&circuit.gates, &circuit.wires);
+
+    for g in order {
+        let gate = &circuit.gates.gatestack[g];
+
+        let mut ins = vec![];
+        for net in gate.net_inputs() {
+            match lits.get(&net) {
+                Some(lit) => ins.push(*lit),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("net {} is never driven", net))),
+            }
+        }
+
+        let lit = match gate {
+            Gates::AND(_) | Gates::NAND(_) => {
+                let mut acc = ins[0];
+                for lit in &ins[1..] {
+                    acc = and(acc, *lit, &mut ands);
+                }
+                if let Gates::NAND(_) = gate { acc ^ 1 } else { acc }
+            },
+            Gates::OR(_) | Gates::NOR(_) => {
+                let mut acc = ins[0] ^ 1;
+                for lit in &ins[1..] {
+                    acc = and(acc, *lit ^ 1, &mut ands);
+                }
+                if let Gates::OR(_) = gate { acc ^ 1 } else { acc }
+            },
+            Gates::XOR(_) | Gates::XNOR(_) => {
+                let mut acc = ins[0];
+                for lit in &ins[1..] {
+                    let left = and(acc, *lit ^ 1, &mut ands);
+                    let right = and(acc ^ 1, *lit, &mut ands);
+                    acc = and(left ^ 1, right ^ 1, &mut ands) ^ 1;
+                }
+                if let Gates::XNOR(_) = gate { acc ^ 1 } else { acc }
+            },
+            Gates::INV(_) => ins[0] ^ 1,
+            Gates::BUF(_) => ins[0],
+            Gates::TRIBUF(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("tri-state buffer on net {} has no AIGER equivalent", circuit.netname(gate.net_output())))),
+            Gates::CELL(gate) => cellliteral(&gate.cell.function, &ins, &and, &mut ands),
+        };
+
+        lits.insert(gate.net_output(), lit);
+    }
+
+    let lookup = |net: u32| -> io::Result<u32> {
+        match lits.get(&net) {
+            Some(lit) => Ok(*lit),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, format!("net {} is never driven", net))),
+        }
+    };
+
+    // a flop with a reset loads D AND NOT reset
+    let mut nexts = vec![];
+    for flop in &circuit.flops {
+        let d = lookup(flop.d)?;
+        nexts.push(match flop.reset {
+            Some(reset) => and(d, lookup(reset)? ^ 1, &mut ands),
+            None => d,
+        });
+    }
+
+    let a = ands.len() as u32;
+    writeln!(out, "{} {} {} {} {} {}", if binary { "aig" } else { "aag" }, i + l + a, i, l, circuit.outputs.len(), a)?;
+
+    if !binary {
+        for k in 0..i {
+            writeln!(out, "{}", 2 * (k + 1))?;
+        }
+    }
+
+    for (k, flop) in circuit.flops.iter().enumerate() {
+        let q = 2 * (i + k as u32 + 1);
+        let next = nexts[k];
+        let init = match flop.init {
+            FiveLogic::ZERO => 0,
+            FiveLogic::ONE => 1,
+            _ => q,
+        };
+
+        if !binary {
+            write!(out, "{} ", q)?;
+        }
+        if init == 0 {
+            writeln!(out, "{}", next)?;
+        } else {
+            writeln!(out, "{} {}", next, init)?;
+        }
+    }
+
+    for net in &circuit.outputs {
+        writeln!(out, "{}", lookup(*net)?)?;
+    }
+
+    for (lhs, rhs0, rhs1) in &ands {
+        if binary {
+            encode(out, lhs - rhs0)?;
+            encode(out, rhs0 - rhs1)?;
+        } else {
+            writeln!(out, "{} {} {}", lhs, rhs0, rhs1)?;
+        }
+    }
+
+    for (k, net) in circuit.inputs.iter().enumerate() {
+        writeln!(out, "i{} {}", k, circuit.netname(*net))?;
+    }
+    for (k, flop) in circuit.flops.iter().enumerate() {
+        writeln!(out, "l{} {}", k, circuit.netname(flop.q))?;
+    }
+    for (k, net) in circuit.outputs.iter().enumerate() {
+        writeln!(out, "o{} {}", k, circuit.netname(*net))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::{parselines, simulate};
+
+    fn run(circuit: &mut Circuit, values: &[FiveLogic]) -> Vec<FiveLogic> {
+        for (net, value) in circuit.inputs.iter().zip(values) {
+            circuit.wires.get_mut(net).unwrap().level = *value;
+        }
+        simulate(&mut circuit.gates, &mut circuit.wires);
+        circuit.outputs.iter().map(|o| circuit.wires[o].level).collect()
+    }
+
+    fn vector(v: usize, width: usize) -> Vec<FiveLogic> {
+        (0..width).map(|b| if (v >> b) & 1 == 1 { FiveLogic::ONE } else { FiveLogic::ZERO }).collect()
+    }
+
+    #[test]
+    fn half_adder() {
+        let text = "aag 7 2 0 2 3\n2\n4\n6\n12\n6 13 15\n12 2 4\n14 3 5\ni0 x\ni1 y\no0 s\no1 c\n";
+        let mut circuit = parseaigerbytes("half.aag", text.as_bytes()).unwrap();
+
+        assert_eq!(circuit.inputs, vec![1, 2]);
+        assert_eq!(circuit.netname(circuit.outputs[1]), "c");
+
+        let one = FiveLogic::ONE;
+        let zero = FiveLogic::ZERO;
+        assert_eq!(run(&mut circuit, &[zero, zero]), vec![zero, zero]);
+        assert_eq!(run(&mut circuit, &[one, zero]), vec![one, zero]);
+        assert_eq!(run(&mut circuit, &[one, one]), vec![zero, one]);
+    }
+
+    #[test]
+    fn latch() {
+        let circuit = parseaigerbytes("toggle.aag", b"aag 1 0 1 1 0\n2 3\n2\n").unwrap();
+
+        assert_eq!(circuit.flops.len(), 1);
+        assert_eq!((circuit.flops[0].q, circuit.flops[0].init), (1, FiveLogic::ZERO));
+        assert_eq!(circuit.gates.gatestack.len(), 1);
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = "INV 1 3\nINV 2 4\nAND 1 4 5\nAND 2 3 6\nOR 5 6 7\nNOR 5 6 8\nNAND 7 1 9\nXNOR 1 2 9 10\nINPUT 1 2 -1\nOUTPUT 7 8 9 10 -1";
+        let mut circuit = parselines("test", text.lines().map(String::from)).unwrap();
+
+        for binary in [false, true] {
+            let mut bytes: Vec<u8> = vec![];
+            writeaiger(&circuit, binary, &mut bytes).unwrap();
+            let mut reread = parseaigerbytes("test", &bytes).unwrap();
+
+            for v in 0..4 {
+                assert_eq!(run(&mut reread, &vector(v, 2)), run(&mut circuit, &vector(v, 2)));
+            }
+        }
+    }
+
+    #[test]
+    fn constants() {
+        let mut circuit = parseaigerbytes("c.aag", b"aag 2 1 0 3 1\n2\n0\n1\n4\n4 2 1\n").unwrap();
+
+        let names: Vec<&str> = circuit.gates.gatestack.iter().map(|g| g.name()).collect();
+        assert_eq!(names, vec!["TIE1", "AND", "TIE0"]);
+        assert_eq!(run(&mut circuit, &[FiveLogic::D]), vec![FiveLogic::ZERO, FiveLogic::ONE, FiveLogic::D]);
+
+        let mut text: Vec<u8> = vec![];
+        writeaiger(&circuit, false, &mut text).unwrap();
+        let mut reread = parseaigerbytes("c.aag", &text).unwrap();
+        assert_eq!(run(&mut reread, &[FiveLogic::D]), vec![FiveLogic::ZERO, FiveLogic::ONE, FiveLogic::D]);
+    }
+
+    #[test]
+    fn binary_encoding() {
+        let circuit = parselines("test", "AND 1 2 3\nINPUT 1 2 -1\nOUTPUT 3 -1".lines().map(String::from)).unwrap();
+
+        let mut bytes: Vec<u8> = vec![];
+        writeaiger(&circuit, true, &mut bytes).unwrap();
+
+        assert_eq!(bytes, b"aig 3 2 0 1 1\n6\n\x02\x02i0 1\ni1 2\no0 3\n".to_vec());
+    }
+
+    #[test]
+    fn errors() {
+        let e = parseaigerbytes("c.aag", b"aag 2 1 0 1 1\n2\n5\n5 2 2\n").err().unwrap();
+        assert_eq!((e.line, e.reason), (4, ParseReason::Unsupported));
+
+        let e = parseaigerbytes("c.aag", b"aag 3 2 0 1 1\n2\n4\n6\n6 2\n").err().unwrap();
+        assert_eq!((e.line, e.reason), (5, ParseReason::MissingOperand));
+
+        // the header points at the field that failed, not at the first optional one
+        let e = parseaigerbytes("c.aag", b"aag 1 1 0 1 0 0 0 1\n").err().unwrap();
+        assert_eq!((e.line, e.column, e.token.as_str(), e.reason), (1, 19, "1", ParseReason::Unsupported));
+        let e = parseaigerbytes("c.aag", b"aag 1 1 x 1 0\n").err().unwrap();
+        assert_eq!((e.column, e.token.as_str(), e.reason), (9, "x", ParseReason::NonNumericNet));
+
+        let e = parseaigerbytes("c.aag", b"aig2 1 1 0 1 0\n").err().unwrap();
+        assert_eq!(e.reason, ParseReason::UnknownGate);
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use crate::fault::{detects, faultlist, inject, Fault, Pin};
+use crate::gates::{invert, simulate, Circuit, FiveLogic, Gates};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Outcome {
+    // input values that detect the fault, X where any value will do
+    Detected(Vec<FiveLogic>),
+    // no input vector can detect the fault
+    Untestable,
+    // the backtrack limit ran out first
+    Aborted,
+}
+
+// What pattern generation made of a fault.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Status {
+    // caught by the pattern of this number
+    Detected(usize),
+    Untestable,
+    Aborted,
+}
+
+// A test for a combinational circuit: the values applied to the inputs and
+// those expected on the outputs of the fault free circuit.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Pattern {
+    pub inputs: Vec<FiveLogic>,
+    pub outputs: Vec<FiveLogic>,
+}
+
+// The value the fault site carries, D or Dnot once the fault is excited.
+fn sitevalue(circuit: &Circuit, fault: &Fault) -> FiveLogic {
+    let gate = &circuit.gates.gatestack[fault.gate];
+    match fault.pin {
+        Pin::Input(k) => gate.input(k),
+        Pin::Output => inject(gate.output(), fault.stuck),
+    }
+}
+
+fn level(circuit: &Circuit, net: u32) -> FiveLogic {
+    circuit.wires.get(&net).map(|w| w.level).unwrap_or(FiveLogic::X)
+}
+
+fn iserror(value: FiveLogic) -> bool {
+    matches!(value, FiveLogic::D | FiveLogic::Dnot)
+}
+
+// What to set next to move the search on, as a net and the value it should
+// take: the fault site to the opposite of its stuck value until the fault is
+// excited, then an unknown input of a gate on the D-frontier to a value that
+// lets the fault effect through. None when the fault can no longer be
+// excited or its effect has nowhere left to go.
+fn objective(circuit: &Circuit, fault: &Fault, drivers: &HashMap<u32,Vec<usize>>) -> Option<(u32, FiveLogic)> {
+    let site = sitevalue(circuit, fault);
+    let gate = &circuit.gates.gatestack[fault.gate];
+
+    if site == FiveLogic::X {
+        let net = match fault.pin {
+            Pin::Input(k) => gate.net_inputs()[k],
+            Pin::Output => gate.net_output(),
+        };
+        return Some((net, invert(&fault.stuck)));
+    }
+    if !iserror(site) {
+        return None;
+    }
+
+    // the D-frontier: gates with an error on an input, or the faulty output
+    // itself, whose output net is still unknown
+    for (i, gate) in circuit.gates.gatestack.iter().enumerate() {
+        let net = gate.net_output();
+        let ins = gate.net_inputs();
+        let source = i == fault.gate && fault.pin == Pin::Output;
+        if level(circuit, net) != FiveLogic::X || !(source || (0..ins.len()).any(|k| iserror(gate.input(k)))) {
+            continue;
+        }
+
+        let open = (0..ins.len()).find(|k| gate.input(*k) == FiveLogic::X);
+        if let (false, Some(k)) = (source, open) {
+            let value = match gate {
+                Gates::AND(_) | Gates::NAND(_) | Gates::TRIBUF(_) => FiveLogic::ONE,
+                _ => FiveLogic::ZERO,
+            };
+            return Some((ins[k], value));
+        }
+
+        // the error reaches a bus left unknown by the other drivers, so turn
+        // one of them off
+        for d in drivers.get(&net).into_iter().flatten().filter(|d| **d != i) {
+            let other = &circuit.gates.gatestack[*d];
+            let pins = other.net_inputs();
+            let pin = match other {
+                Gates::TRIBUF(_) if other.input(1) == FiveLogic::X => Some(1),
+                _ => (0..pins.len()).find(|k| other.input(*k) == FiveLogic::X),
+            };
+            if let Some(k) = pin {
+                return Some((pins[k], FiveLogic::ZERO));
+            }
+        }
+    }
+
+    None
+}
+
+// Walks an objective back through the gates driving it to an unassigned
+// input, flipping the value through inverting gates. Returns the input's
+// position and value.
+fn backtrace(circuit: &Circuit, drivers: &HashMap<u32,Vec<usize>>, assigned: &[FiveLogic], (mut net, mut value): (u32, FiveLogic)) -> Option<(usize, FiveLogic)> {
+    for _ in 0..=circuit.gates.gatestack.len() {
+        if let Some(m) = circuit.inputs.iter().position(|i| *i == net) {
+            return if assigned[m] == FiveLogic::X { Some((m, value)) } else { None };
+        }
+
+        let (gate, k) = drivers.get(&net).into_iter().flatten()
+            .map(|d| &circuit.gates.gatestack[*d])
+            .find_map(|g| (0..g.net_inputs().len()).find(|k| g.input(*k) == FiveLogic::X).map(|k| (g, k)))?;
+
+        value = match gate {
+            Gates::NAND(_) | Gates::NOR(_) | Gates::INV(_) | Gates::XNOR(_) => invert(&value),
+            // a tri-state driver has to be enabled to drive anything
+            Gates::TRIBUF(_) if k == 1 => FiveLogic::ONE,
+            _ => value,
+        };
+        net = gate.net_inputs()[k];
+    }
+
+    None
+}
+
+// PODEM: decides one primary input at a time, each chosen by tracing an
+// objective back from the fault, and undoes the latest decision whenever the
+// fault can no longer be detected. Every input gets both values before the
+// search gives up on a fault, so it is found untestable only if it is.
+pub fn podem(circuit: &mut Circuit, fault: &Fault, limit: usize) -> Outcome {
+    let mut drivers: HashMap<u32,Vec<usize>> = HashMap::new();
+    for (i, g) in circuit.gates.gatestack.iter().enumerate() {
+        drivers.entry(g.net_output()).or_default().push(i);
+    }
+
+    let mut assigned = vec![FiveLogic::X; circuit.inputs.len()];
+    let mut decisions: Vec<(usize, bool)> = vec![];
+    let mut backtracks = 0;
+
+    loop {
+        if detects(circuit, &assigned, fault) {
+            return Outcome::Detected(assigned);
+        }
+
+        // fall back on any open input where the objective leads nowhere
+        let next = objective(circuit, fault, &drivers).map(|goal| {
+            backtrace(circuit, &drivers, &assigned, goal)
+                .or_else(|| assigned.iter().position(|v| *v == FiveLogic::X).map(|m| (m, FiveLogic::ZERO)))
+        });
+
+        if let Some(Some((m, value))) = next {
+            assigned[m] = value;
+            decisions.push((m, false));
+            continue;
+        }
+
+        loop {
+            match decisions.pop() {
+                Some((m, false)) => {
+                    backtracks += 1;
+                    if backtracks > limit {
+                        return Outcome::Aborted;
+                    }
+                    assigned[m] = invert(&assigned[m]);
+                    decisions.push((m, true));
+                    break;
+                },
+                Some((m, true)) => assigned[m] = FiveLogic::X,
+                None => return Outcome::Untestable,
+            }
+        }
+    }
+}
+
+// The fault free output values for a vector.
+pub fn response(circuit: &mut Circuit, inputs: &[FiveLogic]) -> Vec<FiveLogic> {
+    for (net, value) in circuit.inputs.iter().zip(inputs) {
+        if let Some(wire) = circuit.wires.get_mut(net) {
+            wire.level = *value;
+        }
+    }
+    simulate(&mut circuit.gates, &mut circuit.wires);
+
+    circuit.outputs.iter().map(|o| level(circuit, *o)).collect()
+}
+
+// Generates patterns for the stuck-at faults on the first `gates` gates. Each
+// test has its open inputs filled with 0 and is fault simulated against the
+// faults still undetected, which are dropped as they are caught. Returns the
+// patterns and what became of each fault.
+pub fn generate(circuit: &mut Circuit, gates: usize, limit: usize) -> (Vec<Pattern>, Vec<(Fault, Status)>) {
+    let faults: Vec<Fault> = faultlist(&circuit.gates).into_iter().filter(|f| f.gate < gates).collect();
+    let mut results: Vec<(Fault, Option<Status>)> = faults.iter().map(|f| (*f, None)).collect();
+    let mut patterns: Vec<Pattern> = vec![];
+
+    for n in 0..results.len() {
+        if results[n].1.is_some() {
+            continue;
+        }
+
+        match podem(circuit, &results[n].0, limit) {
+            Outcome::Detected(test) => {
+                let inputs: Vec<FiveLogic> = test.iter().map(|v| if *v == FiveLogic::X { FiveLogic::ZERO } else { *v }).collect();
+
+                for result in results.iter_mut().skip(n) {
+                    if result.1.is_none() && detects(circuit, &inputs, &result.0) {
+                        result.1 = Some(Status::Detected(patterns.len()));
+                    }
+                }
+
+                let outputs = response(circuit, &inputs);
+                patterns.push(Pattern { inputs, outputs });
+            },
+            Outcome::Untestable => results[n].1 = Some(Status::Untestable),
+            Outcome::Aborted => results[n].1 = Some(Status::Aborted),
+        }
+    }
+
+    (patterns, results.into_iter().map(|(f, s)| (f, s.unwrap_or(Status::Aborted))).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::parselines;
+
+    fn circuit(text: &str) -> Circuit {
+        parselines("test", text.lines().map(String::from)).unwrap()
+    }
+
+    #[test]
+    fn finds_tests() {
+        let mut circuit = circuit("NAND a b p\nXOR p c q\nMUX2 q d s y\nTRIBUF a e z\nTRIBUF b f z\nINPUT a b c d s e f -1\nOUTPUT y z -1");
+
+        let mut untestable = vec![];
+        for fault in faultlist(&circuit.gates) {
+            match podem(&mut circuit, &fault, 1000) {
+                Outcome::Detected(test) => assert!(detects(&mut circuit, &test, &fault), "{}", fault.describe(&circuit)),
+                Outcome::Untestable => untestable.push(fault.describe(&circuit).to_string()),
+                Outcome::Aborted => panic!("{} aborted", fault.describe(&circuit)),
+            }
+        }
+
+        // a stuck enable only ever leaves the bus floating or in contention
+        // where it differs, and both read as X
+        assert_eq!(untestable, vec![
+            "gate 3 TRIBUF pin in2 (net e) stuck-at-0",
+            "gate 3 TRIBUF pin in2 (net e) stuck-at-1",
+            "gate 4 TRIBUF pin in2 (net f) stuck-at-0",
+            "gate 4 TRIBUF pin in2 (net f) stuck-at-1",
+        ]);
+    }
+
+    #[test]
+    fn redundant_faults() {
+        // q = a | a & b, so the AND is redundant
+        let mut circuit = circuit("AND a b p\nOR a p q\nINPUT a b -1\nOUTPUT q -1");
+
+        let fault = Fault { gate: 0, pin: Pin::Output, stuck: FiveLogic::ZERO };
+        assert_eq!(podem(&mut circuit, &fault, 1000), Outcome::Untestable);
+
+        let fault = Fault { gate: 1, pin: Pin::Input(0), stuck: FiveLogic::ZERO };
+        assert_eq!(podem(&mut circuit, &fault, 1000), Outcome::Detected(vec![FiveLogic::ONE, FiveLogic::ZERO]));
+    }
+
+    #[test]
+    fn fault_dropping() {
+        let mut circuit = circuit("NAND a b p\nNAND c d q\nNAND p q y\nINPUT a b c d -1\nOUTPUT y -1");
+        let (patterns, results) = generate(&mut circuit, usize::MAX, 1000);
+
+        assert!(patterns.len() < results.len() / 2);
+
+        for (fault, status) in &results {
+            let Status::Detected(n) = status else {
+                panic!("{} {:?}", fault.describe(&circuit), status);
+            };
+            let pattern = &patterns[*n];
+            assert!(detects(&mut circuit, &pattern.inputs, fault));
+            assert_eq!(response(&mut circuit, &pattern.inputs), pattern.outputs);
+        }
+    }
+}
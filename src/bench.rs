@@ -0,0 +1,404 @@
+use std::io::{self,Write};
+
+use crate::cells;
+use crate::gates::{read_lines, validname, Circuit, FiveLogic, Gates, NetTable, ParseError, ParseReason, KEYWORDS};
+
+pub fn parsebench(filename: &str) -> Result<Circuit, ParseError> {
+    match read_lines(filename) {
+        Ok(lines) => parsebenchlines(filename, lines.map_while(Result::ok)),
+        Err(_) => Err(ParseError {
+            file: filename.to_string(),
+            line: 0,
+            column: 0,
+            token: String::new(),
+            reason: ParseReason::UnreadableFile,
+        }),
+    }
+}
+
+// A piece of a line and the byte offset it starts at.
+type Token<'a> = (usize, &'a str);
+
+// Trims a piece of a line that starts at `offset`, keeping the offset of what
+// is left.
+fn piece(offset: usize, text: &str) -> Token<'_> {
+    (offset + text.len() - text.trim_start().len(), text.trim())
+}
+
+// A `DEFGATE NAME COUNT BODY` line, defining a gate the same way as in the
+// native netlist format. On failure returns the offending word and why.
+fn definition<'a>(circuit: &Circuit, text: &'a str) -> Result<cells::Cell, (Token<'a>, ParseReason)> {
+    let mut words: Vec<Token> = vec![];
+    let mut offset = 0;
+    for word in text.split([' ', '\t']) {
+        if !word.is_empty() {
+            words.push((offset, word));
+        }
+        offset += word.len() + 1;
+    }
+
+    let (name, count, body) = match &words[..] {
+        [_, name, count, body @ ..] if !body.is_empty() => (*name, *count, body),
+        _ => {
+            let last = words[words.len() - 1];
+            return Err(((last.0 + last.1.len(), last.1), ParseReason::MissingOperand));
+        },
+    };
+
+    let upper = name.1.to_ascii_uppercase();
+    if KEYWORDS.contains(&upper.as_str()) || matches!(upper.as_str(), "NOT" | "BUFF") || circuit.cells.contains_key(name.1) {
+        return Err((name, ParseReason::Redefined));
+    }
+    if !validname(name.1) || name.1.contains('[') {
+        return Err((name, ParseReason::InvalidName));
+    }
+
+    let rows: Vec<&str> = body.iter().map(|w| w.1).collect();
+    cells::definegate(name.1, count.1, &rows).map_err(|bad| {
+        let at = words.iter().skip(2).find(|w| w.1 == bad).copied().unwrap_or(count);
+        (at, ParseReason::InvalidDefinition)
+    })
+}
+
+// Reads the ISCAS-85/89 .bench format:
+//
+//     INPUT(G1)
+//     OUTPUT(G22)
+//     G10 = NAND(G1, G3)
+//     G5 = DFF(G10)
+//     DEFGATE MAJ3 3 00010111
+//
+// DFF takes its D net, and may take a reset net after it. Net names are
+// numbered in order of first appearance and kept in the circuit's name table.
+// A DEFGATE line defines a gate as in the native format, ahead of its use.
+pub fn parsebenchlines<I>(filename: &str, lines: I) -> Result<Circuit, ParseError>
+where I: Iterator<Item = String>, {
+    let mut circuit = Circuit::new();
+    let mut nets = NetTable::new();
+
+    for (n, line) in lines.enumerate() {
+        let text = match line.find('#') {
+            Some(i) => &line[..i],
+            None => &line[..],
+        };
+
+        let error = |(offset, token): Token, reason: ParseReason| ParseError {
+            file: filename.to_string(),
+            line: n + 1,
+            column: offset + 1,
+            token: token.to_string(),
+            reason,
+        };
+
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        if text.split_whitespace().next() == Some("DEFGATE") {
+            let cell = definition(&circuit, text).map_err(|(token, reason)| error(token, reason))?;
+            circuit.cells.insert(cell.name.clone(), cell);
+            continue;
+        }
+
+        let (target, call) = match text.split_once('=') {
+            Some((target, call)) => (Some(piece(0, target)), piece(target.len() + 1, call)),
+            None => (None, piece(0, text)),
+        };
+
+        let (keyword, args) = match (call.1.find('('), call.1.ends_with(')')) {
+            (Some(open), true) => (piece(call.0, &call.1[..open]), (call.0 + open + 1, &call.1[open + 1..call.1.len() - 1])),
+            _ => return Err(error(call, ParseReason::Malformed)),
+        };
+
+        let mut offset = args.0;
+        let mut operands: Vec<Token> = vec![];
+        for arg in args.1.split(',') {
+            operands.push(piece(offset, arg));
+            offset += arg.len() + 1;
+        }
+        let args: Vec<Token> = operands.into_iter().filter(|a| !a.1.is_empty()).collect();
+
+        let gateop = keyword.1.to_ascii_uppercase();
+
+        // only tie cells take no operands
+        if args.is_empty() && circuit.arity(&gateop) != Some(0) {
+            return Err(error(keyword, ParseReason::MissingOperand));
+        }
+
+        match target {
+            None => {
+                if args.len() > 1 {
+                    return Err(error(args[1], ParseReason::WrongOperandCount));
+                }
+
+                match gateop.as_str() {
+                    "INPUT" => circuit.addinput(nets.id(args[0].1)),
+                    "OUTPUT" => circuit.addoutput(nets.id(args[0].1)),
+                    _ => return Err(error(keyword, ParseReason::UnknownGate)),
+                }
+            },
+            Some(target) => {
+                if target.1.is_empty() {
+                    return Err(error(call, ParseReason::Malformed));
+                }
+
+                let out = nets.id(target.1);
+                let ins: Vec<u32> = args.iter().map(|a| nets.id(a.1)).collect();
+
+                if gateop == "DFF" {
+                    match ins[..] {
+                        [d] => circuit.addflop(d, out, FiveLogic::X),
+                        [d, reset] => circuit.addresetflop(d, out, FiveLogic::X, reset),
+                        _ => return Err(error(args[2], ParseReason::WrongOperandCount)),
+                    }
+                    continue;
+                }
+
+                let op = match gateop.as_str() {
+                    "NOT" => "INV",
+                    "BUFF" => "BUF",
+                    // defined gates keep the case they were defined in
+                    _ if circuit.cells.contains_key(keyword.1) => keyword.1,
+                    _ => gateop.as_str(),
+                };
+
+                match circuit.arity(op) {
+                    Some(n) if ins.len() > n => return Err(error(args[n], ParseReason::WrongOperandCount)),
+                    Some(n) if ins.len() < n => return Err(error(keyword, ParseReason::MissingOperand)),
+                    _ => {},
+                }
+
+                if !circuit.addwidegate(op, &ins, out) {
+                    return Err(error(keyword, ParseReason::UnknownGate));
+                }
+            },
+        }
+    }
+
+    circuit.names = nets.names();
+
+    Ok(circuit)
+}
+
+// Writes the circuit back out in .bench syntax. Named nets keep their names
+// and the rest are written by number.
+pub fn writebench<W: Write>(circuit: &Circuit, out: &mut W) -> io::Result<()> {
+    writeln!(out, "# {} inputs", circuit.inputs.len())?;
+    writeln!(out, "# {} outputs", circuit.outputs.len())?;
+    writeln!(out, "# {} gates", circuit.gates.gatestack.len())?;
+    writeln!(out)?;
+
+    // gates defined by the netlist rather than the library, ahead of any use
+    let library = cells::library();
+    let mut defined: Vec<&cells::Cell> = circuit.cells.values().filter(|c| library.get(&c.name) != Some(*c)).collect();
+    defined.sort_by_key(|c| &c.name);
+    for cell in &defined {
+        writeln!(out, "DEFGATE {} {} {}", cell.name, cell.inputs.len(), cells::truthtable(cell))?;
+    }
+    if !defined.is_empty() {
+        writeln!(out)?;
+    }
+
+    for net in &circuit.inputs {
+        writeln!(out, "INPUT({})", circuit.netname(*net))?;
+    }
+    writeln!(out)?;
+
+    for net in &circuit.outputs {
+        writeln!(out, "OUTPUT({})", circuit.netname(*net))?;
+    }
+    writeln!(out)?;
+
+    for gate in &circuit.gates.gatestack {
+        let keyword = match gate {
+            Gates::INV(_) => "NOT",
+            Gates::BUF(_) => "BUFF",
+            _ => gate.name(),
+        };
+
+        let ins: Vec<String> = gate.net_inputs().iter().map(|n| circuit.netname(*n)).collect();
+
+        writeln!(out, "{} = {}({})", circuit.netname(gate.net_output()), keyword, ins.join(", "))?;
+    }
+
+    for flop in &circuit.flops {
+        let ins: Vec<String> = std::iter::once(flop.d).chain(flop.reset).map(|n| circuit.netname(n)).collect();
+        writeln!(out, "{} = DFF({})", circuit.netname(flop.q), ins.join(", "))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::simulate;
+
+    const C17: &str = "# c17
+INPUT(1)
+INPUT(2)
+INPUT(3)
+INPUT(6)
+INPUT(7)
+OUTPUT(22)
+OUTPUT(23)
+
+10 = NAND(1, 3)
+11 = NAND(3, 6)
+16 = NAND(2, 11)
+19 = NAND(11, 7)
+22 = NAND(10, 16)
+23 = NAND(16, 19)
+";
+
+    fn bench(text: &str) -> Result<Circuit, ParseError> {
+        parsebenchlines("test.bench", text.lines().map(String::from))
+    }
+
+    fn run(circuit: &mut Circuit, values: &[FiveLogic]) -> Vec<FiveLogic> {
+        for (net, value) in circuit.inputs.iter().zip(values) {
+            circuit.wires.get_mut(net).unwrap().level = *value;
+        }
+        simulate(&mut circuit.gates, &mut circuit.wires);
+        circuit.outputs.iter().map(|o| circuit.wires[o].level).collect()
+    }
+
+    #[test]
+    fn c17() {
+        let mut circuit = bench(C17).unwrap();
+
+        assert_eq!(circuit.gates.gatestack.len(), 6);
+        assert_eq!(circuit.inputs.len(), 5);
+        assert_eq!(circuit.outputs.len(), 2);
+
+        let one = FiveLogic::ONE;
+        let zero = FiveLogic::ZERO;
+        assert_eq!(run(&mut circuit, &[one, one, one, one, one]), vec![one, zero]);
+        assert_eq!(run(&mut circuit, &[zero, zero, zero, zero, zero]), vec![zero, zero]);
+    }
+
+    #[test]
+    fn multi_input_and_xor() {
+        let mut circuit = bench("INPUT(a)\nINPUT(b)\nINPUT(c)\nOUTPUT(y)\nOUTPUT(p)\ny = nor(a, b, c)\np = XOR(a, b, c)").unwrap();
+
+        let one = FiveLogic::ONE;
+        let zero = FiveLogic::ZERO;
+        assert_eq!(run(&mut circuit, &[zero, zero, zero]), vec![one, zero]);
+        assert_eq!(run(&mut circuit, &[zero, one, zero]), vec![zero, one]);
+        assert_eq!(run(&mut circuit, &[one, one, zero]), vec![zero, zero]);
+        assert_eq!(run(&mut circuit, &[one, one, one]), vec![zero, one]);
+    }
+
+    #[test]
+    fn tie_cells() {
+        let mut circuit = bench("INPUT(a)\nOUTPUT(y)\nOUTPUT(z)\none = TIE1()\ny = AND(a, one)\nz = TIE0()").unwrap();
+
+        assert_eq!(run(&mut circuit, &[FiveLogic::D]), vec![FiveLogic::D, FiveLogic::ZERO]);
+
+        let mut text: Vec<u8> = vec![];
+        writebench(&circuit, &mut text).unwrap();
+        assert!(String::from_utf8(text).unwrap().ends_with("one = TIE1()\ny = AND(a, one)\nz = TIE0()\n"));
+
+        let e = bench("INPUT(a)\nOUTPUT(y)\ny = AND()").err().unwrap();
+        assert_eq!(e.reason, ParseReason::MissingOperand);
+    }
+
+    #[test]
+    fn defined_gates() {
+        let mut circuit = crate::gates::parselines("test", "DEFGATE MAJ3 3 00010111\nDEFGATE nand2 2 1110\nMAJ3 a b c m\nnand2 m a y\nINPUT a b c -1\nOUTPUT y -1".lines().map(String::from)).unwrap();
+
+        let mut text: Vec<u8> = vec![];
+        writebench(&circuit, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("\nDEFGATE MAJ3 3 00010111\nDEFGATE nand2 2 1110\n\nINPUT(a)\n"));
+        assert!(text.ends_with("m = MAJ3(a, b, c)\ny = nand2(m, a)\n"));
+
+        let mut reread = bench(&text).unwrap();
+        let values = [FiveLogic::ZERO, FiveLogic::ONE];
+        for v in 0..8 {
+            let vector: Vec<FiveLogic> = (0..3).map(|b| values[(v >> b) & 1]).collect();
+            assert_eq!(run(&mut reread, &vector), run(&mut circuit, &vector));
+        }
+
+        let e = bench("DEFGATE MAJ3 3 0001011").err().unwrap();
+        assert_eq!((e.column, e.reason), (16, ParseReason::InvalidDefinition));
+        let e = bench("DEFGATE NOT 1 10").err().unwrap();
+        assert_eq!((e.column, e.reason), (9, ParseReason::Redefined));
+        let e = bench("DEFGATE F 1 10\nDEFGATE F 1 01").err().unwrap();
+        assert_eq!((e.line, e.reason), (2, ParseReason::Redefined));
+        let e = bench("DEFGATE F 2").err().unwrap();
+        assert_eq!(e.reason, ParseReason::MissingOperand);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut circuit = bench(C17).unwrap();
+
+        let mut text: Vec<u8> = vec![];
+        writebench(&circuit, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        let mut reread = bench(&text).unwrap();
+
+        assert_eq!(reread.gates.gatestack.len(), circuit.gates.gatestack.len());
+        assert!(text.contains("\n22 = NAND(10, 16)\n"));
+
+        let values = [FiveLogic::ZERO, FiveLogic::ONE];
+        for v in 0..32 {
+            let vector: Vec<FiveLogic> = (0..5).map(|b| values[(v >> b) & 1]).collect();
+            assert_eq!(run(&mut reread, &vector), run(&mut circuit, &vector));
+        }
+    }
+
+    #[test]
+    fn writes_numbered_nets() {
+        let circuit = crate::gates::parselines("test", "INV 1 3\nINV 2 4\nAND 1 4 5\nAND 2 3 6\nOR 5 6 7\nINPUT 1 2 -1\nOUTPUT 7 -1".lines().map(String::from)).unwrap();
+
+        let mut text: Vec<u8> = vec![];
+        writebench(&circuit, &mut text).unwrap();
+
+        assert_eq!(String::from_utf8(text).unwrap(), "# 2 inputs\n# 1 outputs\n# 5 gates\n\nINPUT(1)\nINPUT(2)\n\nOUTPUT(7)\n\n3 = NOT(1)\n4 = NOT(2)\n5 = AND(1, 4)\n6 = AND(2, 3)\n7 = OR(5, 6)\n");
+    }
+
+    #[test]
+    fn errors() {
+        let e = bench("INPUT(a)\nb = MUX(a, a)").err().unwrap();
+        assert_eq!((e.line, e.column, e.reason), (2, 5, ParseReason::UnknownGate));
+
+        let e = bench("INPUT(a)\nb = NOT(a").err().unwrap();
+        assert_eq!((e.line, e.reason), (2, ParseReason::Malformed));
+
+        let e = bench("INPUT(a)\nb = AND()").err().unwrap();
+        assert_eq!((e.line, e.reason), (2, ParseReason::MissingOperand));
+
+        let e = bench("INPUT(a, b)").err().unwrap();
+        assert_eq!((e.line, e.column, e.reason), (1, 10, ParseReason::WrongOperandCount));
+
+        // the column is where the operand is, not where its name first appears
+        let e = bench("INPUT(G1)\nG1 = AND(G1x, G1)\nG2 = NOT(G1, G1)").err().unwrap();
+        assert_eq!((e.line, e.column, e.token.as_str()), (3, 14, "G1"));
+    }
+
+    #[test]
+    fn sequential() {
+        let s27 = "# s27\nINPUT(G0)\nINPUT(G1)\nINPUT(G2)\nINPUT(G3)\nOUTPUT(G17)\nG5 = DFF(G10)\nG6 = DFF(G11)\nG7 = DFF(G13)\nG14 = NOT(G0)\nG17 = NOT(G11)\nG8 = AND(G14, G6)\nG15 = OR(G12, G8)\nG16 = OR(G3, G8)\nG9 = NAND(G16, G15)\nG10 = NOR(G14, G11)\nG11 = NOR(G5, G9)\nG12 = NOR(G1, G7)\nG13 = NOR(G2, G12)";
+        let circuit = bench(s27).unwrap();
+
+        assert_eq!((circuit.gates.gatestack.len(), circuit.flops.len()), (10, 3));
+        assert_eq!((circuit.netname(circuit.flops[0].d), circuit.netname(circuit.flops[0].q)), ("G10".to_string(), "G5".to_string()));
+        assert!(crate::lint::lint(&circuit).is_empty());
+
+        let mut text: Vec<u8> = vec![];
+        writebench(&circuit, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.ends_with("G5 = DFF(G10)\nG6 = DFF(G11)\nG7 = DFF(G13)\n"));
+        let reread = bench(&text).unwrap();
+        let flops = |c: &Circuit| c.flops.iter().map(|f| (c.netname(f.d), c.netname(f.q))).collect::<Vec<_>>();
+        assert_eq!(flops(&reread), flops(&circuit));
+
+        let circuit = bench("INPUT(d)\nINPUT(r)\nOUTPUT(q)\nq = DFF(d, r)").unwrap();
+        assert_eq!(circuit.netname(circuit.flops[0].reset.unwrap()), "r");
+
+        let e = bench("INPUT(d)\nq = DFF(d, d, d)").err().unwrap();
+        assert_eq!((e.line, e.reason), (2, ParseReason::WrongOperandCount));
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::io::{self,Write};
+
+use crate::gates::{read_lines, tokenize, Circuit, FiveLogic, Gates, NetTable, ParseError, ParseReason};
+
+pub fn parseblif(filename: &str) -> Result<Circuit, ParseError> {
+    match read_lines(filename) {
+        Ok(lines) => parsebliflines(filename, lines.map_while(Result::ok)),
+        Err(_) => Err(ParseError {
+            file: filename.to_string(),
+            line: 0,
+            column: 0,
+            token: String::new(),
+            reason: ParseReason::UnreadableFile,
+        }),
+    }
+}
+
+// A .names block waiting for its cover rows.
+struct Names {
+    line: usize,
+    nets: Vec<u32>,
+    rows: Vec<(String, char)>,
+}
+
+// Joins lines ending in a backslash with the line after and drops comments,
+// keeping the number of the line each logical line started on.
+fn logicallines<I>(lines: I) -> Vec<(usize,String)>
+where I: Iterator<Item = String>, {
+    let mut joined = vec![];
+    let mut pending: Option<(usize,String)> = None;
+
+    for (n, line) in lines.enumerate() {
+        let text = match line.find('#') {
+            Some(i) => &line[..i],
+            None => &line[..],
+        };
+        let text = text.trim_end();
+
+        let (start, mut acc) = pending.take().unwrap_or((n + 1, String::new()));
+
+        match text.strip_suffix('\\') {
+            Some(head) => {
+                acc.push_str(head);
+                acc.push(' ');
+                pending = Some((start, acc));
+            },
+            None => {
+                acc.push_str(text);
+                joined.push((start, acc));
+            },
+        }
+    }
+
+    if let Some(p) = pending {
+        joined.push(p);
+    }
+
+    joined
+}
+
+// Reads the first .model of a BLIF file. Each .names cover is built from
+// INV, AND and OR gates: one AND per cube over the literals it cares about,
+// an OR across the cubes, and an INV on the result for an off-set cover.
+// Constant covers become TIE0 and TIE1 cells. Latches become flops with their
+// initial value.
+pub fn parsebliflines<I>(filename: &str, lines: I) -> Result<Circuit, ParseError>
+where I: Iterator<Item = String>, {
+    let mut circuit = Circuit::new();
+    let mut nets = NetTable::new();
+    let mut inverted: HashMap<u32,u32> = HashMap::new();
+    let mut names: Option<Names> = None;
+
+    let lines = logicallines(lines);
+
+    for (n, line) in &lines {
+        let tokens = tokenize(line);
+
+        let error = |(column, token): (usize, &str), reason: ParseReason| ParseError {
+            file: filename.to_string(),
+            line: *n,
+            column,
+            token: token.to_string(),
+            reason,
+        };
+
+        let Some(&keyword) = tokens.first() else {
+            continue;
+        };
+
+        if !keyword.1.starts_with('.') {
+            let Some(block) = names.as_mut() else {
+                return Err(error(keyword, ParseReason::Malformed));
+            };
+
+            let (cube, value) = match (block.nets.len(), tokens.len()) {
+                (1, 1) => (String::new(), tokens[0]),
+                (_, 2) => (tokens[0].1.to_string(), tokens[1]),
+                _ => return Err(error(keyword, ParseReason::WrongOperandCount)),
+            };
+
+            if cube.len() != block.nets.len() - 1 || cube.chars().any(|c| c != '0' && c != '1' && c != '-') {
+                return Err(error(keyword, ParseReason::Malformed));
+            }
+            let value = match value.1 {
+                "1" => '1',
+                "0" => '0',
+                _ => return Err(error(value, ParseReason::Malformed)),
+            };
+
+            block.rows.push((cube, value));
+            continue;
+        }
+
+        if let Some(block) = names.take() {
+            buildcover(&mut circuit, &mut nets, &mut inverted, block, filename)?;
+        }
+
+        match keyword.1 {
+            ".model" => {},
+            ".inputs" => {
+                for t in &tokens[1..] {
+                    circuit.addinput(nets.id(t.1));
+                }
+            },
+            ".outputs" => {
+                for t in &tokens[1..] {
+                    circuit.addoutput(nets.id(t.1));
+                }
+            },
+            ".names" => {
+                if tokens.len() < 2 {
+                    return Err(error(keyword, ParseReason::MissingOperand));
+                }
+                names = Some(Names {
+                    line: *n,
+                    nets: tokens[1..].iter().map(|t| nets.id(t.1)).collect(),
+                    rows: vec![],
+                });
+            },
+            ".latch" => {
+                // .latch input output [type control] [init]
+                let init = match tokens.len() {
+                    1 | 2 => return Err(error(keyword, ParseReason::MissingOperand)),
+                    3 | 5 => FiveLogic::X,
+                    4 | 6 => match tokens[tokens.len() - 1] {
+                        (_, "0") => FiveLogic::ZERO,
+                        (_, "1") => FiveLogic::ONE,
+                        (_, "2" | "3") => FiveLogic::X,
+                        init => return Err(error(init, ParseReason::Malformed)),
+                    },
+                    _ => return Err(error(tokens[6], ParseReason::WrongOperandCount)),
+                };
+                circuit.addflop(nets.id(tokens[1].1), nets.id(tokens[2].1), init);
+            },
+            ".end" => break,
+            _ => return Err(error(keyword, ParseReason::UnknownGate)),
+        }
+    }
+
+    if let Some(block) = names.take() {
+        buildcover(&mut circuit, &mut nets, &mut inverted, block, filename)?;
+    }
+
+    circuit.names = nets.names();
+
+    Ok(circuit)
+}
+
+fn buildcover(circuit: &mut Circuit, nets: &mut NetTable, inverted: &mut HashMap<u32,u32>, block: Names, filename: &str) -> Result<(), ParseError> {
+    let out = block.nets[block.nets.len() - 1];
+    let ins = &block.nets[..block.nets.len() - 1];
+
+    let cubes: Vec<&String> = block.rows.iter().map(|r| &r.0).collect();
+
+    // a cover with no rows, or a cube with no literals, is a constant
+    if cubes.is_empty() || cubes.iter().any(|c| c.chars().all(|l| l == '-')) {
+        let one = block.rows.first().is_some_and(|r| r.1 == '1');
+        circuit.addgate(if one { "TIE1" } else { "TIE0" }, &[], out);
+        return Ok(());
+    }
+
+    let offset = block.rows[0].1 == '0';
+    if block.rows.iter().any(|r| (r.1 == '0') != offset) {
+        return Err(ParseError {
+            file: filename.to_string(),
+            line: block.line,
+            column: 1,
+            token: ".names".to_string(),
+            reason: ParseReason::Malformed,
+        });
+    }
+
+    // a cover of one literal is a buffer or an inverter on its net
+    if let [cube] = cubes[..] {
+        let care: Vec<(u32,char)> = ins.iter().copied().zip(cube.chars()).filter(|(_, l)| *l != '-').collect();
+        if let [(net, l)] = care[..] {
+            circuit.addgate(if (l == '1') != offset { "BUF" } else { "INV" }, &[net], out);
+            return Ok(());
+        }
+    }
+
+    let sum = if offset { nets.fresh() } else { out };
+
+    let mut terms: Vec<u32> = vec![];
+    for cube in &cubes {
+        let mut literals: Vec<u32> = vec![];
+        for (net, l) in ins.iter().zip(cube.chars()) {
+            match l {
+                '1' => literals.push(*net),
+                '0' => {
+                    let inv = *inverted.entry(*net).or_insert_with(|| {
+                        let inv = nets.fresh();
+                        circuit.addgate("INV", &[*net], inv);
+                        inv
+                    });
+                    literals.push(inv);
+                },
+                _ => {},
+            }
+        }
+
+        if literals.len() == 1 {
+            terms.push(literals[0]);
+        } else {
+            // a single cube drives the sum itself
+            let t = if cubes.len() == 1 { sum } else { nets.fresh() };
+            circuit.addwidegate("AND", &literals, t);
+            terms.push(t);
+        }
+    }
+
+    if terms != [sum] {
+        circuit.addwidegate("OR", &terms, sum);
+    }
+    if offset {
+        circuit.addgate("INV", &[sum], out);
+    }
+
+    Ok(())
+}
+
+// The on-set cover of a gate with the given number of inputs.
+fn cover(gate: &Gates, inputs: usize) -> Vec<String> {
+    let single = |on: char| -> Vec<String> {
+        (0..inputs).map(|i| (0..inputs).map(|j| if i == j { on } else { '-' }).collect::<String>() + " 1").collect()
+    };
+
+    match gate {
+        Gates::AND(_) => vec!["1".repeat(inputs) + " 1"],
+        Gates::NOR(_) => vec!["0".repeat(inputs) + " 1"],
+        Gates::OR(_) => single('1'),
+        Gates::NAND(_) => single('0'),
+        Gates::XOR(_) | Gates::XNOR(_) => {
+            let odd = matches!(gate, Gates::XOR(_));
+            (0..1usize << inputs)
+                .filter(|row| (row.count_ones() & 1 == 1) == odd)
+                .map(|row| (0..inputs).map(|i| if (row >> i) & 1 == 1 { '1' } else { '0' }).collect::<String>() + " 1")
+                .collect()
+        },
+        Gates::INV(_) => vec!["0 1".to_string()],
+        Gates::BUF(_) => vec!["1 1".to_string()],
+        Gates::TRIBUF(_) => unreachable!("tri-state buffers are rejected by writeblif"),
+        // a tie cell's cover is a bare 1, or no rows at all
+        Gates::CELL(gate) if inputs == 0 => match gate.cell.function.eval(&[]) {
+            FiveLogic::ONE => vec!["1".to_string()],
+            _ => vec![],
+        },
+        Gates::CELL(gate) => (0..1usize << inputs)
+            .filter(|row| {
+                let values: Vec<FiveLogic> = (0..inputs).map(|i| if (row >> i) & 1 == 1 { FiveLogic::ONE } else { FiveLogic::ZERO }).collect();
+                gate.cell.function.eval(&values) == FiveLogic::ONE
+            })
+            .map(|row| (0..inputs).map(|i| if (row >> i) & 1 == 1 { '1' } else { '0' }).collect::<String>() + " 1")
+            .collect(),
+    }
+}
+
+// Writes the circuit as a BLIF model with one .names block per gate and a
+// .latch per flop. Unnamed nets are written by number.
+pub fn writeblif<W: Write>(circuit: &Circuit, model: &str, out: &mut W) -> io::Result<()> {
+    writeln!(out, ".model {}", model)?;
+
+    let inputs: Vec<String> = circuit.inputs.iter().map(|n| circuit.netname(*n)).collect();
+    writeln!(out, ".inputs {}", inputs.join(" "))?;
+
+    let outputs: Vec<String> = circuit.outputs.iter().map(|n| circuit.netname(*n)).collect();
+    writeln!(out, ".outputs {}", outputs.join(" "))?;
+
+    for flop in &circuit.flops {
+        if flop.reset.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("flop on net {} has a reset, which a BLIF latch cannot express", circuit.netname(flop.q))));
+        }
+        let init = match flop.init {
+            FiveLogic::ZERO => 0,
+            FiveLogic::ONE => 1,
+            _ => 3,
+        };
+        writeln!(out, ".latch {} {} {}", circuit.netname(flop.d), circuit.netname(flop.q), init)?;
+    }
+
+    for gate in &circuit.gates.gatestack {
+        if let Gates::TRIBUF(_) = gate {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("tri-state buffer on net {} has no BLIF equivalent", circuit.netname(gate.net_output()))));
+        }
+
+        let mut pins: Vec<String> = gate.net_inputs().iter().map(|n| circuit.netname(*n)).collect();
+        pins.push(circuit.netname(gate.net_output()));
+
+        writeln!(out, ".names {}", pins.join(" "))?;
+        for row in cover(gate, pins.len() - 1) {
+            writeln!(out, "{}", row)?;
+        }
+    }
+
+    writeln!(out, ".end")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::simulate;
+
+    fn blif(text: &str) -> Result<Circuit, ParseError> {
+        parsebliflines("test.blif", text.lines().map(String::from))
+    }
+
+    fn run(circuit: &mut Circuit, values: &[FiveLogic]) -> Vec<FiveLogic> {
+        for (net, value) in circuit.inputs.iter().zip(values) {
+            circuit.wires.get_mut(net).unwrap().level = *value;
+        }
+        simulate(&mut circuit.gates, &mut circuit.wires);
+        circuit.outputs.iter().map(|o| circuit.wires[o].level).collect()
+    }
+
+    const MAJ: &str = ".model maj
+# majority of three, and its complement
+.inputs a b \\
+  c
+.outputs y z
+.names a b c y
+11- 1
+1-1 1
+-11 1
+.names a b c z
+11- 0
+1-1 0
+-11 0
+.end
+";
+
+    #[test]
+    fn covers() {
+        let mut circuit = blif(MAJ).unwrap();
+
+        assert_eq!(circuit.inputs.len(), 3);
+        assert_eq!(circuit.outputs.len(), 2);
+
+        let one = FiveLogic::ONE;
+        let zero = FiveLogic::ZERO;
+        assert_eq!(run(&mut circuit, &[one, one, zero]), vec![one, zero]);
+        assert_eq!(run(&mut circuit, &[one, zero, zero]), vec![zero, one]);
+        assert_eq!(run(&mut circuit, &[zero, one, one]), vec![one, zero]);
+    }
+
+    #[test]
+    fn negative_literals_and_latch() {
+        let mut circuit = blif(".model t\n.inputs a b\n.outputs y\n.latch y q re clk 1\n.names a b q y\n10- 1\n--0 1\n.end").unwrap();
+
+        assert_eq!(circuit.flops.len(), 1);
+        assert_eq!(circuit.flops[0].init, FiveLogic::ONE);
+        assert_eq!(circuit.netname(circuit.flops[0].q), "q");
+
+        let one = FiveLogic::ONE;
+        let zero = FiveLogic::ZERO;
+        assert_eq!(run(&mut circuit, &[one, zero]), vec![one]);
+        assert_eq!(run(&mut circuit, &[one, one]), vec![zero]);
+    }
+
+    #[test]
+    fn constants() {
+        let mut circuit = blif(".model t\n.inputs a\n.outputs y z w\n.names y\n1\n.names z\n.names a w\n- 0\n.end").unwrap();
+
+        let names: Vec<&str> = circuit.gates.gatestack.iter().map(|g| g.name()).collect();
+        assert_eq!(names, vec!["TIE1", "TIE0", "TIE0"]);
+        assert_eq!(run(&mut circuit, &[FiveLogic::X]), vec![FiveLogic::ONE, FiveLogic::ZERO, FiveLogic::ZERO]);
+
+        let mut text: Vec<u8> = vec![];
+        writeblif(&circuit, "t", &mut text).unwrap();
+        assert!(String::from_utf8(text).unwrap().ends_with(".names y\n1\n.names z\n.names w\n.end\n"));
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut circuit = blif(MAJ).unwrap();
+
+        let mut text: Vec<u8> = vec![];
+        writeblif(&circuit, "maj", &mut text).unwrap();
+        let mut reread = blif(&String::from_utf8(text).unwrap()).unwrap();
+
+        let values = [FiveLogic::ZERO, FiveLogic::ONE];
+        for v in 0..8 {
+            let vector: Vec<FiveLogic> = (0..3).map(|b| values[(v >> b) & 1]).collect();
+            assert_eq!(run(&mut reread, &vector), run(&mut circuit, &vector));
+        }
+
+        // a second trip reads back the covers the first one wrote, gate for gate
+        let mut text: Vec<u8> = vec![];
+        writeblif(&reread, "maj", &mut text).unwrap();
+        let again = blif(&String::from_utf8(text).unwrap()).unwrap();
+        assert_eq!(again.gates.gatestack.len(), reread.gates.gatestack.len());
+        assert_eq!(again.wires.len(), reread.wires.len());
+    }
+
+    #[test]
+    fn numbered_names() {
+        // the NAND comes back with inverters on nets the file never named,
+        // which must not take the names 5 and 6
+        let bench = "INPUT(1)\nINPUT(2)\nOUTPUT(9)\n3 = NAND(1, 2)\n5 = NOT(3)\n6 = NOT(5)\n9 = AND(6, 1)";
+        let mut circuit = crate::bench::parsebenchlines("test.bench", bench.lines().map(String::from)).unwrap();
+
+        let mut text: Vec<u8> = vec![];
+        writeblif(&circuit, "t", &mut text).unwrap();
+        let reread = blif(&String::from_utf8(text).unwrap()).unwrap();
+
+        let mut text: Vec<u8> = vec![];
+        crate::bench::writebench(&reread, &mut text).unwrap();
+        let mut back = crate::bench::parsebenchlines("test.bench", String::from_utf8(text).unwrap().lines().map(String::from)).unwrap();
+
+        assert!(crate::lint::lint(&back).is_empty());
+        let values = [FiveLogic::ZERO, FiveLogic::ONE];
+        for v in 0..4 {
+            let vector: Vec<FiveLogic> = (0..2).map(|b| values[(v >> b) & 1]).collect();
+            assert_eq!(run(&mut back, &vector), run(&mut circuit, &vector));
+        }
+    }
+
+    #[test]
+    fn writes_gate_covers() {
+        let circuit = crate::gates::parselines("test", "NAND 1 2 3\nNOR 1 3 4\nINPUT 1 2 -1\nOUTPUT 4 -1".lines().map(String::from)).unwrap();
+
+        let mut text: Vec<u8> = vec![];
+        writeblif(&circuit, "t", &mut text).unwrap();
+
+        assert_eq!(String::from_utf8(text).unwrap(), ".model t\n.inputs 1 2\n.outputs 4\n.names 1 2 3\n0- 1\n-0 1\n.names 1 3 4\n00 1\n.end\n");
+    }
+
+    #[test]
+    fn errors() {
+        let e = blif(".model t\n.inputs a b\n.names a b y\n1 1\n").err().unwrap();
+        assert_eq!((e.line, e.reason), (4, ParseReason::Malformed));
+
+        let e = blif(".model t\n.subckt foo a=b\n").err().unwrap();
+        assert_eq!((e.line, e.column, e.reason), (2, 1, ParseReason::UnknownGate));
+
+        // the column is the bad token's own, not an earlier one with the same text
+        let e = blif(".model t\n.latch x b x\n").err().unwrap();
+        assert_eq!((e.line, e.column, e.token.as_str(), e.reason), (2, 12, "x", ParseReason::Malformed));
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize,Serialize};
+
+use crate::gates::FiveLogic;
+use crate::logic::Logic;
+
+// The built-in cell library. Each line is a cell name, its input pins in
+// netlist order, then its output pin and boolean function. Functions use !
+// for NOT, & for AND, ^ for XOR and | for OR, binding in that order, with
+// parentheses for grouping, and 0 and 1 for constants. Tie cells have no
+// inputs.
+pub const LIBRARY: &str = "
+# cell   inputs        output = function
+MUX2     A B S         Y = !S & A | S & B
+AOI21    A1 A2 B       Y = !(A1 & A2 | B)
+AOI22    A1 A2 B1 B2   Y = !(A1 & A2 | B1 & B2)
+OAI21    A1 A2 B       Y = !((A1 | A2) & B)
+OAI22    A1 A2 B1 B2   Y = !((A1 | A2) & (B1 | B2))
+TIE0                   Y = 0
+TIE1                   Y = 1
+";
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum Expr {
+    Pin(usize),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Xor(Vec<Expr>),
+    Table(Vec<bool>),
+    Const(bool),
+}
+
+impl Expr {
+    pub fn eval<L: Logic>(&self, inputs: &[L]) -> L {
+        match self {
+            Expr::Pin(k) => inputs.get(*k).copied().unwrap_or(L::UNKNOWN),
+            Expr::Not(e) => e.eval(inputs).not(),
+            Expr::And(terms) => terms.iter().fold(L::ONE, |acc, t| acc.and(t.eval(inputs))),
+            Expr::Or(terms) => terms.iter().fold(L::ZERO, |acc, t| acc.or(t.eval(inputs))),
+            Expr::Xor(terms) => terms.iter().fold(L::ZERO, |acc, t| acc.xor(t.eval(inputs))),
+            Expr::Table(table) => L::lookup(table, inputs),
+            Expr::Const(true) => L::ONE,
+            Expr::Const(false) => L::ZERO,
+        }
+    }
+}
+
+// Reads a truth table for five-valued inputs. The good and faulty machines
+// are looked up separately, and each comes out known only when every row
+// the X inputs could select agrees, so a table is never more pessimistic than
+// the function it describes.
+pub fn lookup(table: &[bool], inputs: &[FiveLogic]) -> FiveLogic {
+    let n = table.len().trailing_zeros() as usize;
+    let value = |k: usize| inputs.get(k).copied().unwrap_or(FiveLogic::X);
+
+    let machine = |good: bool| -> Option<bool> {
+        let known: Vec<Option<bool>> = (0..n).map(|k| match value(k) {
+            FiveLogic::ZERO => Some(false),
+            FiveLogic::ONE => Some(true),
+            FiveLogic::D => Some(good),
+            FiveLogic::Dnot => Some(!good),
+            FiveLogic::X | FiveLogic::Z => None,
+        }).collect();
+
+        let mut seen = [false, false];
+        for (row, out) in table.iter().enumerate() {
+            let matches = known.iter().enumerate().all(|(k, v)| v.is_none_or(|v| v == rowbit(row, k, n)));
+            if matches {
+                seen[*out as usize] = true;
+            }
+        }
+
+        match seen {
+            [true, false] => Some(false),
+            [false, true] => Some(true),
+            _ => None,
+        }
+    };
+
+    match (machine(true), machine(false)) {
+        (Some(true), Some(true)) => FiveLogic::ONE,
+        (Some(false), Some(false)) => FiveLogic::ZERO,
+        (Some(true), Some(false)) => FiveLogic::D,
+        (Some(false), Some(true)) => FiveLogic::Dnot,
+        _ => FiveLogic::X,
+    }
+}
+
+// The value of pin k in a truth table row. The first pin is the most
+// significant bit, so rows count up as they are usually written.
+pub fn rowbit(row: usize, k: usize, n: usize) -> bool {
+    (row >> (n - 1 - k)) & 1 == 1
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Cell {
+    pub name: String,
+    pub inputs: Vec<String>,
+    pub output: String,
+    pub function: Expr,
+}
+
+// Splits a function into pin names and single character operators.
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut name = String::new();
+
+    for c in text.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            name.push(c);
+            continue;
+        }
+        if !name.is_empty() {
+            tokens.push(std::mem::take(&mut name));
+        }
+        if !c.is_whitespace() {
+            tokens.push(c.to_string());
+        }
+    }
+    if !name.is_empty() {
+        tokens.push(name);
+    }
+
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    pins: &'a [String],
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.as_str())
+    }
+
+    fn error(&self) -> String {
+        self.peek().unwrap_or("end of function").to_string()
+    }
+
+    // One precedence level: operands from `next` separated by `op`.
+    fn list(&mut self, op: &str, next: fn(&mut Self) -> Result<Expr, String>, make: fn(Vec<Expr>) -> Expr) -> Result<Expr, String> {
+        let mut terms = vec![next(self)?];
+        while self.peek() == Some(op) {
+            self.pos += 1;
+            terms.push(next(self)?);
+        }
+
+        if terms.len() == 1 {
+            return Ok(terms.remove(0));
+        }
+        Ok(make(terms))
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        self.list("|", Self::xor, Expr::Or)
+    }
+
+    fn xor(&mut self) -> Result<Expr, String> {
+        self.list("^", Self::and, Expr::Xor)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.list("&", Self::unary, Expr::And)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some("!") => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            },
+            Some("(") => {
+                self.pos += 1;
+                let e = self.or()?;
+                if self.peek() != Some(")") {
+                    return Err(self.error());
+                }
+                self.pos += 1;
+                Ok(e)
+            },
+            Some(c @ ("0" | "1")) => {
+                let value = c == "1";
+                self.pos += 1;
+                Ok(Expr::Const(value))
+            },
+            Some(name) => match self.pins.iter().position(|p| p == name) {
+                Some(k) => {
+                    self.pos += 1;
+                    Ok(Expr::Pin(k))
+                },
+                None => Err(self.error()),
+            },
+            None => Err(self.error()),
+        }
+    }
+}
+
+// Parses a function of the given pins. On failure returns the token where
+// parsing stopped.
+pub fn parsefunction(text: &str, pins: &[String]) -> Result<Expr, String> {
+    let mut p = Parser { tokens: tokenize(text), pos: 0, pins };
+    let e = p.or()?;
+
+    if p.pos != p.tokens.len() {
+        return Err(p.error());
+    }
+    Ok(e)
+}
+
+// Parses one library line, `NAME IN... OUT = FUNCTION`. On failure returns the
+// offending token.
+pub fn parsecell(line: &str) -> Result<Cell, String> {
+    let Some((ports, function)) = line.split_once('=') else {
+        return Err(line.trim().to_string());
+    };
+
+    let ports: Vec<&str> = ports.split_whitespace().collect();
+    if ports.len() < 2 {
+        return Err(ports.last().unwrap_or(&"=").to_string());
+    }
+
+    let inputs: Vec<String> = ports[1..ports.len() - 1].iter().map(|p| p.to_string()).collect();
+
+    Ok(Cell {
+        name: ports[0].to_string(),
+        function: parsefunction(function, &inputs)?,
+        inputs,
+        output: ports[ports.len() - 1].to_string(),
+    })
+}
+
+// The largest user-defined gate, which keeps its truth table to 64k rows.
+pub const MAXPINS: usize = 16;
+
+// Defines a gate with `count` inputs, pins A, B, C and so on and output Y,
+// from either a truth table or a sum of products:
+//
+//     MAJ3 3 00010111        output for rows 000, 001, ... 111
+//     MAJ3 3 11- 1-1 -11     on-set cubes, one character per input
+//
+// Both become a table. On failure returns the offending token.
+pub fn definegate(name: &str, count: &str, body: &[&str]) -> Result<Cell, String> {
+    let n = match count.parse::<usize>() {
+        Ok(n) if (1..=MAXPINS).contains(&n) => n,
+        _ => return Err(count.to_string()),
+    };
+    let rows = 1usize << n;
+
+    let table: Vec<bool> = match body {
+        [] => return Err(count.to_string()),
+        [table] if table.len() == rows && table.chars().all(|c| c == '0' || c == '1') => {
+            table.chars().map(|c| c == '1').collect()
+        },
+        cubes => {
+            if let Some(bad) = cubes.iter().find(|c| c.len() != n || !c.chars().all(|c| matches!(c, '0' | '1' | '-'))) {
+                return Err(bad.to_string());
+            }
+            (0..rows).map(|row| cubes.iter().any(|cube| {
+                cube.chars().enumerate().all(|(k, c)| c == '-' || (c == '1') == rowbit(row, k, n))
+            })).collect()
+        },
+    };
+
+    Ok(Cell {
+        name: name.to_string(),
+        inputs: (0..n).map(|k| ((b'A' + k as u8) as char).to_string()).collect(),
+        output: "Y".to_string(),
+        function: Expr::Table(table),
+    })
+}
+
+// A cell's truth table as written by `definegate`, one output per row.
+pub fn truthtable(cell: &Cell) -> String {
+    let n = cell.inputs.len();
+
+    (0..1usize << n).map(|row| {
+        let values: Vec<FiveLogic> = (0..n).map(|k| if rowbit(row, k, n) { FiveLogic::ONE } else { FiveLogic::ZERO }).collect();
+        if cell.function.eval(&values) == FiveLogic::ONE { '1' } else { '0' }
+    }).collect()
+}
+
+pub fn library() -> HashMap<String,Cell> {
+    LIBRARY.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| parsecell(l).ok())
+        .map(|c| (c.name.clone(), c))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(bits: &str) -> Vec<FiveLogic> {
+        bits.chars().map(|c| match c {
+            '0' => FiveLogic::ZERO,
+            '1' => FiveLogic::ONE,
+            'D' => FiveLogic::D,
+            'd' => FiveLogic::Dnot,
+            _ => FiveLogic::X,
+        }).collect()
+    }
+
+    #[test]
+    fn builtin_cells() {
+        let cells = library();
+
+        assert_eq!(cells.len(), 7);
+        assert_eq!(cells["AOI22"].inputs, vec!["A1", "A2", "B1", "B2"]);
+        assert_eq!(cells["AOI22"].output, "Y");
+
+        // every cell against its truth table
+        let check = |name: &str, table: fn(&[bool]) -> bool| {
+            let cell = &cells[name];
+            let n = cell.inputs.len();
+            for row in 0..1usize << n {
+                let bits: Vec<bool> = (0..n).map(|b| (row >> b) & 1 == 1).collect();
+                let ins: Vec<FiveLogic> = bits.iter().map(|b| if *b { FiveLogic::ONE } else { FiveLogic::ZERO }).collect();
+                let want = if table(&bits) { FiveLogic::ONE } else { FiveLogic::ZERO };
+                assert_eq!(cell.function.eval(&ins), want, "{} row {}", name, row);
+            }
+        };
+
+        check("MUX2", |b| if b[2] { b[1] } else { b[0] });
+        check("AOI21", |b| !(b[0] && b[1] || b[2]));
+        check("AOI22", |b| !(b[0] && b[1] || b[2] && b[3]));
+        check("OAI21", |b| !((b[0] || b[1]) && b[2]));
+        check("OAI22", |b| !((b[0] || b[1]) && (b[2] || b[3])));
+        check("TIE0", |_| false);
+        check("TIE1", |_| true);
+    }
+
+    #[test]
+    fn five_valued() {
+        let cells = library();
+        let mux = &cells["MUX2"].function;
+
+        assert_eq!(mux.eval(&values("D01")), FiveLogic::ZERO);
+        assert_eq!(mux.eval(&values("D10")), FiveLogic::D);
+        assert_eq!(mux.eval(&values("01D")), FiveLogic::D);
+        assert_eq!(mux.eval(&values("11X")), FiveLogic::X);
+
+        let aoi = &cells["AOI21"].function;
+        assert_eq!(aoi.eval(&values("1D0")), FiveLogic::Dnot);
+        assert_eq!(aoi.eval(&values("XD1")), FiveLogic::ZERO);
+    }
+
+    #[test]
+    fn defined_gates() {
+        let table = definegate("MAJ3", "3", &["00010111"]).unwrap();
+        let cubes = definegate("MAJ3", "3", &["11-", "1-1", "-11"]).unwrap();
+
+        assert_eq!(table, cubes);
+        assert_eq!(table.inputs, vec!["A", "B", "C"]);
+        assert_eq!(truthtable(&table), "00010111");
+        assert_eq!(truthtable(&library()["MUX2"]), "00011011");
+
+        // a table sees through X where the other inputs decide the output
+        assert_eq!(table.function.eval(&values("11X")), FiveLogic::ONE);
+        assert_eq!(table.function.eval(&values("D1X")), FiveLogic::X);
+        assert_eq!(table.function.eval(&values("D10")), FiveLogic::D);
+        assert_eq!(table.function.eval(&values("dD1")), FiveLogic::ONE);
+        assert_eq!(table.function.eval(&values("0d0")), FiveLogic::ZERO);
+
+        assert_eq!(definegate("BAD", "0", &["1"]), Err("0".to_string()));
+        assert_eq!(definegate("BAD", "2", &[]), Err("2".to_string()));
+        assert_eq!(definegate("BAD", "2", &["1x"]), Err("1x".to_string()));
+        assert_eq!(definegate("BAD", "3", &["0001"]), Err("0001".to_string()));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parsecell("MAJ A B C Y = A & B | A & C | B & D"), Err("D".to_string()));
+        assert_eq!(parsecell("BAD A Y = (A"), Err("end of function".to_string()));
+        assert_eq!(parsecell("BAD A Y A"), Err("BAD A Y A".to_string()));
+        assert_eq!(parsecell("BAD = 1"), Err("BAD".to_string()));
+        assert_eq!(parsefunction("A & 0", &["A".to_string()]), Ok(Expr::And(vec![Expr::Pin(0), Expr::Const(false)])));
+        assert!(parsefunction("A ^ B ^ !C", &["A".to_string(), "B".to_string(), "C".to_string()]).is_ok());
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap,HashMap,HashSet};
+
+use crate::gates::{simulate, Circuit, FiveLogic, Gates};
+
+// Finds the nets held at 0 or 1 whatever the circuit is fed. Every primary
+// input and flop output is left at X, so any net that still simulates to a
+// known value is constant, driven from tie cells.
+pub fn constants(circuit: &Circuit) -> BTreeMap<u32,FiveLogic> {
+    let mut gates = circuit.gates.clone();
+    let mut wires = circuit.wires.clone();
+
+    for wire in wires.values_mut() {
+        wire.level = FiveLogic::X;
+    }
+    simulate(&mut gates, &mut wires);
+
+    wires.values()
+        .filter(|w| matches!(w.level, FiveLogic::ZERO | FiveLogic::ONE))
+        .map(|w| (w.net, w.level))
+        .collect()
+}
+
+// Rebuilds the circuit with its constants folded in. A gate whose output is
+// constant becomes a tie cell, and AND, OR and XOR style gates lose the
+// constant inputs that no longer affect them, down to a BUF or INV. Cells keep
+// all their pins. Gates left driving nothing that reaches an output or a flop
+// are swept away.
+pub fn propagate(circuit: &Circuit) -> Circuit {
+    let constant = constants(circuit);
+    let value = |net: &u32| constant.get(net).copied();
+
+    let mut gates: Vec<(String, Vec<u32>, u32)> = vec![];
+    for gate in &circuit.gates.gatestack {
+        let out = gate.net_output();
+        let ins = gate.net_inputs();
+
+        let folded = match (value(&out), gate) {
+            (Some(FiveLogic::ONE), _) => ("TIE1".to_string(), vec![]),
+            (Some(_), _) => ("TIE0".to_string(), vec![]),
+            (None, Gates::AND(_) | Gates::NAND(_)) => {
+                (gate.name().to_string(), ins.into_iter().filter(|n| value(n) != Some(FiveLogic::ONE)).collect())
+            },
+            (None, Gates::OR(_) | Gates::NOR(_)) => {
+                (gate.name().to_string(), ins.into_iter().filter(|n| value(n) != Some(FiveLogic::ZERO)).collect())
+            },
+            (None, Gates::XOR(_) | Gates::XNOR(_)) => {
+                // every constant 1 input flips the parity
+                let ones = ins.iter().filter(|n| value(n) == Some(FiveLogic::ONE)).count();
+                let xor = matches!(gate, Gates::XOR(_)) == (ones % 2 == 0);
+                let op = if xor { "XOR" } else { "XNOR" };
+                (op.to_string(), ins.into_iter().filter(|n| value(n).is_none()).collect())
+            },
+            (None, _) => (gate.name().to_string(), ins),
+        };
+
+        gates.push((folded.0, folded.1, out));
+    }
+
+    // keep the gates that feed an output or a flop, working back from them
+    let mut drivers: HashMap<u32,Vec<usize>> = HashMap::new();
+    for (i, gate) in gates.iter().enumerate() {
+        drivers.entry(gate.2).or_default().push(i);
+    }
+
+    let mut live: HashSet<usize> = HashSet::new();
+    let mut pending: Vec<u32> = circuit.outputs.iter().copied()
+        .chain(circuit.flops.iter().flat_map(|f| std::iter::once(f.d).chain(f.reset)))
+        .collect();
+    while let Some(net) = pending.pop() {
+        for i in drivers.get(&net).into_iter().flatten() {
+            if live.insert(*i) {
+                pending.extend(&gates[*i].1);
+            }
+        }
+    }
+
+    let mut simplified = Circuit::new();
+    simplified.cells = circuit.cells.clone();
+
+    for (i, (op, ins, out)) in gates.iter().enumerate() {
+        if live.contains(&i) {
+            simplified.addwidegate(op, ins, *out);
+        }
+    }
+    for net in &circuit.inputs {
+        simplified.addinput(*net);
+    }
+    for net in &circuit.outputs {
+        simplified.addoutput(*net);
+    }
+    for flop in &circuit.flops {
+        match flop.reset {
+            Some(reset) => simplified.addresetflop(flop.d, flop.q, flop.init, reset),
+            None => simplified.addflop(flop.d, flop.q, flop.init),
+        }
+    }
+
+    simplified.names = circuit.names.iter()
+        .filter(|(net, _)| simplified.wires.contains_key(net))
+        .map(|(net, name)| (*net, name.clone()))
+        .collect();
+
+    simplified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::parselines;
+
+    fn circuit(text: &str) -> Circuit {
+        parselines("test", text.lines().map(String::from)).unwrap()
+    }
+
+    #[test]
+    fn tie_cells() {
+        let circuit = circuit("TIE0 zero\nTIE1 one\nAND a zero p\nOR p b q\nNAND a one b r\nXOR one a b s\nMUX2 a b one t\nINPUT a b -1\nOUTPUT q r s t -1");
+        let found = constants(&circuit);
+
+        let names: Vec<String> = found.iter().map(|(net, v)| format!("{}={:?}", circuit.netname(*net), v)).collect();
+        assert_eq!(names, vec!["zero=ZERO", "one=ONE", "p=ZERO"]);
+    }
+
+    #[test]
+    fn folding() {
+        let original = circuit("TIE0 zero\nTIE1 one\nAND a zero p\nOR p b q\nNAND a one b r\nXOR one a b s\nMUX2 a b one t\nINV zero u\nINPUT a b -1\nOUTPUT q r s t -1");
+        let circuit = propagate(&original);
+
+        let gates: Vec<String> = circuit.gates.gatestack.iter().map(|g| {
+            let ins: Vec<String> = g.net_inputs().iter().map(|n| circuit.netname(*n)).collect();
+            format!("{} {} {}", g.name(), ins.join(" "), circuit.netname(g.net_output()))
+        }).collect();
+
+        // p folds into q, which is left a buffer, and u and the TIE0 cells,
+        // which reach nothing, are gone
+        assert_eq!(gates, vec!["TIE1  one", "BUF b q", "NAND a b r", "XNOR a b s", "MUX2 a b one t"]);
+        assert_eq!(circuit.netname(circuit.outputs[0]), "q");
+        assert!(!circuit.names.values().any(|n| n == "u"));
+
+        let (mut a, mut b) = (original, circuit);
+        for v in 0..4 {
+            let vector = [v & 1, v >> 1];
+            for c in [&mut a, &mut b] {
+                for (net, bit) in c.inputs.iter().zip(vector) {
+                    c.wires.get_mut(net).unwrap().level = if bit == 1 { FiveLogic::ONE } else { FiveLogic::ZERO };
+                }
+                simulate(&mut c.gates, &mut c.wires);
+            }
+            for o in &a.outputs {
+                assert_eq!(a.wires[o].level, b.wires[o].level);
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::io::{self,Write};
+
+use crate::gates::{levelname, Circuit, FiveLogic};
+
+// Net names from Verilog escaped identifiers can hold any character, so
+// quotes and backslashes are escaped inside DOT strings.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Edge attributes for a net. With values on, each edge is labeled with the
+// net's simulated value and D/Dnot edges are drawn thick and red so fault
+// propagation paths stand out.
+fn edgestyle(circuit: &Circuit, net: u32, values: bool) -> String {
+    if !values {
+        return format!("label=\"{}\"", escape(&circuit.netname(net)));
+    }
+
+    let level = circuit.wires.get(&net).map(|w| w.level).unwrap_or(FiveLogic::X);
+
+    let color = match level {
+        FiveLogic::D | FiveLogic::Dnot => ", color=red, fontcolor=red, penwidth=2.5",
+        FiveLogic::ONE => ", color=blue, fontcolor=blue",
+        FiveLogic::ZERO => ", color=darkgreen, fontcolor=darkgreen",
+        FiveLogic::X => ", color=gray50, fontcolor=gray50",
+        FiveLogic::Z => ", color=orange, fontcolor=orange, style=dashed",
+    };
+
+    format!("label=\"{}={}\"{}", escape(&circuit.netname(net)), levelname(level), color)
+}
+
+// Writes the circuit as a Graphviz digraph laid out left to right. Gates are
+// nodes labeled with their type, primary inputs and outputs are separate
+// nodes at the edges of the drawing, and every connection is an edge labeled
+// with its net name.
+pub fn writedot<W: Write>(circuit: &Circuit, values: bool, out: &mut W) -> io::Result<()> {
+    writeln!(out, "digraph circuit {{")?;
+    writeln!(out, "  rankdir=LR;")?;
+    writeln!(out, "  node [fontname=\"Helvetica\"];")?;
+    writeln!(out, "  edge [fontname=\"Helvetica\", fontsize=10];")?;
+    writeln!(out)?;
+
+    // the node that drives each net
+    let mut drivers: HashMap<u32,Vec<String>> = HashMap::new();
+
+    writeln!(out, "  {{ rank=source;")?;
+    for net in &circuit.inputs {
+        writeln!(out, "    in{} [label=\"{}\", shape=circle];", net, escape(&circuit.netname(*net)))?;
+        drivers.entry(*net).or_default().push(format!("in{}", net));
+    }
+    writeln!(out, "  }}")?;
+
+    writeln!(out, "  {{ rank=sink;")?;
+    for net in &circuit.outputs {
+        writeln!(out, "    out{} [label=\"{}\", shape=doublecircle];", net, escape(&circuit.netname(*net)))?;
+    }
+    writeln!(out, "  }}")?;
+    writeln!(out)?;
+
+    for (i, gate) in circuit.gates.gatestack.iter().enumerate() {
+        let shape = match gate.name() {
+            "INV" | "BUF" => "triangle",
+            _ => "box",
+        };
+        writeln!(out, "  g{} [label=\"{}\", shape={}];", i, escape(gate.name()), shape)?;
+        drivers.entry(gate.net_output()).or_default().push(format!("g{}", i));
+    }
+
+    for (i, flop) in circuit.flops.iter().enumerate() {
+        writeln!(out, "  ff{} [label=\"DFF\", shape=box, style=bold];", i)?;
+        drivers.entry(flop.q).or_default().push(format!("ff{}", i));
+    }
+    writeln!(out)?;
+
+    let edges = |net: u32, sink: String, out: &mut W| -> io::Result<()> {
+        if let Some(from) = drivers.get(&net) {
+            for d in from {
+                writeln!(out, "  {} -> {} [{}];", d, sink, edgestyle(circuit, net, values))?;
+            }
+        }
+        Ok(())
+    };
+
+    for (i, gate) in circuit.gates.gatestack.iter().enumerate() {
+        for net in gate.net_inputs() {
+            edges(net, format!("g{}", i), out)?;
+        }
+    }
+    for (i, flop) in circuit.flops.iter().enumerate() {
+        edges(flop.d, format!("ff{}", i), out)?;
+        if let Some(reset) = flop.reset {
+            edges(reset, format!("ff{}", i), out)?;
+        }
+    }
+    for net in &circuit.outputs {
+        edges(*net, format!("out{}", net), out)?;
+    }
+
+    writeln!(out, "}}")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::{parselines, simulate};
+
+    const XOR: &str = "INV 1 3\nINV 2 4\nAND 1 4 5\nAND 2 3 6\nOR 5 6 7\nINPUT 1 2 -1\nOUTPUT 7 -1";
+
+    #[test]
+    fn structure() {
+        let circuit = parselines("test", XOR.lines().map(String::from)).unwrap();
+
+        let mut text: Vec<u8> = vec![];
+        writedot(&circuit, false, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+
+        assert!(text.starts_with("digraph circuit {\n  rankdir=LR;\n"));
+        assert!(text.contains("  g2 [label=\"AND\", shape=box];\n"));
+        assert!(text.contains("  g0 [label=\"INV\", shape=triangle];\n"));
+        assert!(text.contains("  in1 -> g2 [label=\"1\"];\n"));
+        assert!(text.contains("  g1 -> g2 [label=\"4\"];\n"));
+        assert!(text.contains("  g4 -> out7 [label=\"7\"];\n"));
+        assert_eq!(text.matches(" -> ").count(), 9);
+    }
+
+    #[test]
+    fn escaped_names() {
+        let mut circuit = parselines("test", XOR.lines().map(String::from)).unwrap();
+        circuit.names.insert(1, "a\"b".to_string());
+        circuit.names.insert(7, "c\\d".to_string());
+
+        let mut text: Vec<u8> = vec![];
+        writedot(&circuit, false, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+
+        assert!(text.contains("  in1 [label=\"a\\\"b\", shape=circle];\n"));
+        assert!(text.contains("  g4 -> out7 [label=\"c\\\\d\"];\n"));
+    }
+
+    #[test]
+    fn colored_values() {
+        let mut circuit = parselines("test", XOR.lines().map(String::from)).unwrap();
+
+        circuit.wires.get_mut(&1).unwrap().level = FiveLogic::D;
+        circuit.wires.get_mut(&2).unwrap().level = FiveLogic::ZERO;
+        simulate(&mut circuit.gates, &mut circuit.wires);
+
+        let mut text: Vec<u8> = vec![];
+        writedot(&circuit, true, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+
+        assert!(text.contains("  g4 -> out7 [label=\"7=D\", color=red, fontcolor=red, penwidth=2.5];\n"));
+        assert!(text.contains("  in2 -> g3 [label=\"2=0\", color=darkgreen, fontcolor=darkgreen];\n"));
+        assert!(text.contains("  g3 -> g4 [label=\"6=0\""));
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::{BTreeMap,HashMap,HashSet};
+use std::fmt;
+
+use crate::gates::{simulatepins, Circuit, FiveLogic, GateStack, Gates, Gate, Wire};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Pin {
+    Input(usize),
+    Output,
+}
+
+// A single stuck-at fault on one pin of one gate. Faults sit on gate and cell
+// pins rather than on nets, so a net with fanout has a separate fault site on
+// each gate input it feeds.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Fault {
+    pub gate: usize,
+    pub pin: Pin,
+    pub stuck: FiveLogic,
+}
+
+// The name of a gate pin: the library pin name for cells, in1..inN and out
+// for the built-in gates.
+pub fn pinname(gate: &Gates, pin: Pin) -> String {
+    match (gate, pin) {
+        (Gates::CELL(cell), Pin::Input(k)) => cell.cell.inputs[k].clone(),
+        (Gates::CELL(cell), Pin::Output) => cell.cell.output.clone(),
+        (_, Pin::Input(k)) => format!("in{}", k + 1),
+        (_, Pin::Output) => "out".to_string(),
+    }
+}
+
+pub struct Described<'a> {
+    fault: &'a Fault,
+    circuit: &'a Circuit,
+}
+
+impl fmt::Display for Described<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let gate = &self.circuit.gates.gatestack[self.fault.gate];
+        let net = match self.fault.pin {
+            Pin::Input(k) => gate.net_inputs()[k],
+            Pin::Output => gate.net_output(),
+        };
+        let stuck = if self.fault.stuck == FiveLogic::ONE { 1 } else { 0 };
+
+        write!(f, "gate {} {} pin {} (net {}) stuck-at-{}",
+            self.fault.gate, gate.name(), pinname(gate, self.fault.pin), self.circuit.netname(net), stuck)
+    }
+}
+
+impl Fault {
+    pub fn describe<'a>(&'a self, circuit: &'a Circuit) -> Described<'a> {
+        Described { fault: self, circuit }
+    }
+}
+
+// Every pin stuck-at-0 and stuck-at-1 fault, gate by gate, inputs before the
+// output.
+pub fn faultlist(gates: &GateStack) -> Vec<Fault> {
+    let mut faults = vec![];
+
+    for (i, gate) in gates.gatestack.iter().enumerate() {
+        let pins = (0..gate.net_inputs().len()).map(Pin::Input).chain(std::iter::once(Pin::Output));
+        for pin in pins {
+            for stuck in [FiveLogic::ZERO, FiveLogic::ONE] {
+                faults.push(Fault { gate: i, pin, stuck });
+            }
+        }
+    }
+
+    faults
+}
+
+// The value a faulty pin carries: D or Dnot where the good value differs
+// from the stuck value, otherwise the good value.
+pub fn inject(good: FiveLogic, stuck: FiveLogic) -> FiveLogic {
+    match (good, stuck) {
+        (FiveLogic::ONE, FiveLogic::ZERO) => FiveLogic::D,
+        (FiveLogic::ZERO, FiveLogic::ONE) => FiveLogic::Dnot,
+        _ => good,
+    }
+}
+
+// Simulates the circuit with the fault in place, so every net carries the
+// good and faulty values together.
+pub fn faultsimulate(gates: &mut GateStack, wires: &mut HashMap<u32,Wire>, fault: &Fault) {
+    // on a bus an output fault sits on this driver, ahead of resolution
+    simulatepins(gates, wires, &|gate, pin, value| {
+        if gate == fault.gate && pin == fault.pin { inject(value, fault.stuck) } else { value }
+    });
+}
+
+// Applies a vector to the primary inputs and reports whether the fault shows
+// up as D or Dnot on a primary output.
+pub fn detects(circuit: &mut Circuit, values: &[FiveLogic], fault: &Fault) -> bool {
+    for (net, value) in circuit.inputs.iter().zip(values) {
+        if let Some(wire) = circuit.wires.get_mut(net) {
+            wire.level = *value;
+        }
+    }
+
+    faultsimulate(&mut circuit.gates, &mut circuit.wires, fault);
+
+    circuit.outputs.iter().any(|o| matches!(circuit.wires.get(o).map(|w| w.level), Some(FiveLogic::D) | Some(FiveLogic::Dnot)))
+}
+
+// Whether constants on a gate's other inputs keep a fault effect on one pin
+// from ever reaching its output: with D on the pin and the known constants on
+// the rest, the output comes out 0 or 1. The other inputs are X for the
+// built-in gates, where that is exact, and tried at every 0 and 1 for cells,
+// whose functions may see through an X.
+fn blocked(gate: &Gates, pin: usize, constants: &BTreeMap<u32,FiveLogic>) -> bool {
+    let nets = gate.net_inputs();
+    let free: Vec<usize> = (0..nets.len()).filter(|k| *k != pin && !constants.contains_key(&nets[*k])).collect();
+    let tries = if matches!(gate, Gates::CELL(_)) { 1usize << free.len() } else { 1 };
+
+    let mut gate = gate.clone();
+    for row in 0..tries {
+        for (k, net) in nets.iter().enumerate() {
+            let value = match (k == pin, constants.get(net), free.iter().position(|f| *f == k)) {
+                (true, _, _) => FiveLogic::D,
+                (_, Some(value), _) => *value,
+                (_, _, Some(bit)) if tries > 1 => if (row >> bit) & 1 == 1 { FiveLogic::ONE } else { FiveLogic::ZERO },
+                _ => FiveLogic::X,
+            };
+            gate.setinput(k, value);
+        }
+        gate.eval();
+
+        if !matches!(gate.output(), FiveLogic::ZERO | FiveLogic::ONE) {
+            return false;
+        }
+    }
+
+    true
+}
+
+// The faults no test can detect because of constant nets. A pin held at the
+// value it is stuck at is never excited, and a pin whose every path to an
+// output or flop is blocked by constants is never observed.
+pub fn untestable(circuit: &Circuit, constants: &BTreeMap<u32,FiveLogic>) -> Vec<Fault> {
+    let gates = &circuit.gates.gatestack;
+
+    let mut open: HashSet<(usize, usize)> = HashSet::new();
+    for (i, gate) in gates.iter().enumerate() {
+        for k in 0..gate.net_inputs().len() {
+            if !blocked(gate, k, constants) {
+                open.insert((i, k));
+            }
+        }
+    }
+
+    // grow the observable nets back from the outputs through open pins
+    let mut observable: HashSet<u32> = circuit.outputs.iter().copied()
+        .chain(circuit.flops.iter().flat_map(|f| std::iter::once(f.d).chain(f.reset)))
+        .collect();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, gate) in gates.iter().enumerate() {
+            if !observable.contains(&gate.net_output()) {
+                continue;
+            }
+            for (k, net) in gate.net_inputs().iter().enumerate() {
+                if open.contains(&(i, k)) && observable.insert(*net) {
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    faultlist(&circuit.gates).into_iter().filter(|f| {
+        let gate = &gates[f.gate];
+        let (net, seen) = match f.pin {
+            Pin::Input(k) => (gate.net_inputs()[k], open.contains(&(f.gate, k)) && observable.contains(&gate.net_output())),
+            Pin::Output => (gate.net_output(), observable.contains(&gate.net_output())),
+        };
+
+        constants.get(&net) == Some(&f.stuck) || !seen
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::parselines;
+
+    fn circuit(text: &str) -> Circuit {
+        parselines("test", text.lines().map(String::from)).unwrap()
+    }
+
+    #[test]
+    fn pin_faults() {
+        let circuit = circuit("INV s 4\nMUX2 a b s y\nINPUT a b s -1\nOUTPUT y 4 -1");
+        let faults = faultlist(&circuit.gates);
+
+        // two per pin: INV in and out, MUX2 A B S and Y
+        assert_eq!(faults.len(), 12);
+        assert_eq!(faults[8], Fault { gate: 1, pin: Pin::Input(2), stuck: FiveLogic::ZERO });
+        assert_eq!(faults[8].describe(&circuit).to_string(), "gate 1 MUX2 pin S (net s) stuck-at-0");
+        assert_eq!(faults[1].describe(&circuit).to_string(), "gate 0 INV pin in1 (net s) stuck-at-1");
+    }
+
+    #[test]
+    fn constant_faults() {
+        let circuit = circuit("TIE0 zero\nAND a zero p\nOR p b q\nTIE1 one\nMUX2 a b one t\nINPUT a b -1\nOUTPUT q t -1");
+        let constants = crate::constants::constants(&circuit);
+
+        let faults: Vec<String> = untestable(&circuit, &constants).iter().map(|f| f.describe(&circuit).to_string()).collect();
+        assert_eq!(faults, vec![
+            "gate 0 TIE0 pin Y (net zero) stuck-at-0",
+            "gate 1 AND pin in1 (net a) stuck-at-0",
+            "gate 1 AND pin in1 (net a) stuck-at-1",
+            "gate 1 AND pin in2 (net zero) stuck-at-0",
+            "gate 1 AND pin out (net p) stuck-at-0",
+            "gate 2 OR pin in1 (net p) stuck-at-0",
+            "gate 3 TIE1 pin Y (net one) stuck-at-1",
+            "gate 4 MUX2 pin A (net a) stuck-at-0",
+            "gate 4 MUX2 pin A (net a) stuck-at-1",
+            "gate 4 MUX2 pin S (net one) stuck-at-1",
+        ]);
+    }
+
+    #[test]
+    fn detection() {
+        let mut circuit = circuit("MUX2 a b s y\nINPUT a b s -1\nOUTPUT y -1");
+        let (zero, one) = (FiveLogic::ZERO, FiveLogic::ONE);
+
+        let select = Fault { gate: 0, pin: Pin::Input(2), stuck: FiveLogic::ZERO };
+        assert!(detects(&mut circuit, &[zero, one, one], &select));
+        assert!(!detects(&mut circuit, &[one, one, one], &select));
+        assert!(!detects(&mut circuit, &[zero, one, zero], &select));
+
+        let output = Fault { gate: 0, pin: Pin::Output, stuck: FiveLogic::ONE };
+        assert!(detects(&mut circuit, &[zero, one, zero], &output));
+        assert_eq!(circuit.wires[&circuit.outputs[0]].level, FiveLogic::Dnot);
+    }
+}
@@ -1,34 +1,51 @@
 use std::fs::File;
 use std::io::{self,BufRead};
 use std::path::Path;
-use std::str::FromStr;
-use std::collections::HashMap;
+use std::collections::{BTreeSet,HashMap,VecDeque};
+use std::fmt;
+use serde::{Deserialize,Serialize};
 
+use crate::cells::{self, Cell};
+use crate::fault::Pin;
+use crate::logic::{Logic, ThreeLogic};
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum FiveLogic {
+    #[serde(rename = "0")]
     ZERO,
+    #[serde(rename = "1")]
     ONE,
     D,
+    #[serde(rename = "d")]
     Dnot,
     X,
+    // high impedance, the value of a bus nothing is driving
+    Z,
 }
 
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
 pub enum Gates {
     AND(ANDGate),
     OR(ORGate),
     NAND(NANDGate),
     NOR(NORGate),
+    XOR(XORGate),
+    XNOR(XNORGate),
     INV(NOTGate),
     BUF(BUFGate),
+    TRIBUF(TRIBUFGate),
+    CELL(CellGate),
 }
 
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum WireType{
     PrimaryInput,
     PrimaryOutput,
     Net,
 }
 
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Wire{
     pub net: u32,
     pub fanout: Vec<u32>,
@@ -37,70 +54,56 @@ pub struct Wire{
     //pub level: FiveLogic,
 }
 
-fn invert(value: &FiveLogic) -> FiveLogic {
-    if *value == FiveLogic::ONE {
-        FiveLogic::ZERO
-    } else if *value == FiveLogic::ZERO {
-        FiveLogic::ONE
-    } else if *value == FiveLogic::D {
-        FiveLogic::Dnot
-    } else if *value == FiveLogic::Dnot {
-        FiveLogic::D
-    } else if *value == FiveLogic::X {
-        FiveLogic::X
-    } else {
-        FiveLogic::X
+pub fn invert(value: &FiveLogic) -> FiveLogic {
+    match value {
+        FiveLogic::ONE => FiveLogic::ZERO,
+        FiveLogic::ZERO => FiveLogic::ONE,
+        FiveLogic::D => FiveLogic::Dnot,
+        FiveLogic::Dnot => FiveLogic::D,
+        FiveLogic::X | FiveLogic::Z => FiveLogic::X,
     }
 }
 
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ANDGate {
-    pub input_a: FiveLogic,
-    pub input_b: FiveLogic,
+    pub inputs: Vec<FiveLogic>,
     pub output: FiveLogic,
-    pub net_in_a: u32,
-    pub net_in_b: u32,
+    pub net_in: Vec<u32>,
     pub net_out: u32,
 }
 
 /*impl ANDGate {
     pub fn new() -> Self {
         ANDGate {
-            input_a: FiveLogic::ZERO,
-            input_b: FiveLogic::ZERO,
+            inputs: vec![],
             output: FiveLogic::ZERO,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![],
             net_out: 0,
         }
     }
 }*/
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ORGate {
-    pub input_a: FiveLogic,
-    pub input_b: FiveLogic,
+    pub inputs: Vec<FiveLogic>,
     pub output: FiveLogic,
-    pub net_in_a: u32,
-    pub net_in_b: u32,
+    pub net_in: Vec<u32>,
     pub net_out: u32,
 }
 
 /*impl ORGate {
     pub fn new() -> Self {
         ORGate {
-            input_a: FiveLogic::ZERO,
-            input_b: FiveLogic::ZERO,
+            inputs: vec![],
             output: FiveLogic::ZERO,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![],
             net_out: 0,
         }
     }
 }*/
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct NOTGate {
     pub input_a: FiveLogic,
     pub output: FiveLogic,
@@ -119,53 +122,73 @@ pub struct NOTGate {
     }
 }*/
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct NANDGate {
-    pub input_a: FiveLogic,
-    pub input_b: FiveLogic,
+    pub inputs: Vec<FiveLogic>,
     pub output: FiveLogic,
-    pub net_in_a: u32,
-    pub net_in_b: u32,
+    pub net_in: Vec<u32>,
     pub net_out: u32,
 }
 
 /*impl NANDGate {
     pub fn new() -> Self {
         NANDGate {
-            input_a: FiveLogic::ZERO,
-            input_b: FiveLogic::ZERO,
+            inputs: vec![],
             output: FiveLogic::ZERO,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![],
             net_out: 0,
         }
     }
 }*/
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct NORGate {
-    pub input_a: FiveLogic,
-    pub input_b: FiveLogic,
+    pub inputs: Vec<FiveLogic>,
     pub output: FiveLogic,
-    pub net_in_a: u32,
-    pub net_in_b: u32,
+    pub net_in: Vec<u32>,
     pub net_out: u32,
 }
 
 /*impl NORGate {
     pub fn new() -> Self {
         NORGate {
-            input_a: FiveLogic::ZERO,
-            input_b: FiveLogic::ZERO,
+            inputs: vec![],
             output: FiveLogic::ZERO,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![],
             net_out: 0,
         }
     }
 }*/
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+// XOR and XNOR are odd and even parity across all of their inputs.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct XORGate {
+    pub inputs: Vec<FiveLogic>,
+    pub output: FiveLogic,
+    pub net_in: Vec<u32>,
+    pub net_out: u32,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct XNORGate {
+    pub inputs: Vec<FiveLogic>,
+    pub output: FiveLogic,
+    pub net_in: Vec<u32>,
+    pub net_out: u32,
+}
+
+// An instance of a library cell. The cell's function is evaluated over the
+// input pins in the order the library lists them.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CellGate {
+    pub cell: Cell,
+    pub inputs: Vec<FiveLogic>,
+    pub output: FiveLogic,
+    pub net_in: Vec<u32>,
+    pub net_out: u32,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct BUFGate {
     pub input_a: FiveLogic,
     pub output: FiveLogic,
@@ -173,6 +196,16 @@ pub struct BUFGate {
     pub net_out: u32,
 }
 
+// A tri-state buffer: input 0 is the data and input 1 the enable. Disabled it
+// lets go of its output, which floats to Z.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TRIBUFGate {
+    pub inputs: Vec<FiveLogic>,
+    pub output: FiveLogic,
+    pub net_in: Vec<u32>,
+    pub net_out: u32,
+}
+
 /*impl BUFGate {
     pub fn new() -> Self {
         BUFGate {
@@ -185,553 +218,1532 @@ pub struct BUFGate {
 }*/
 
 
+// A gate's function is written once over the Logic operators, so every value
+// system simulates the same gate model. eval() runs it five-valued on the
+// values loaded onto the gate's own pins.
 pub trait Gate {
+    fn evaluate<L: Logic>(&self, inputs: &[L]) -> L;
     fn eval(&mut self);
     //fn new(&self) -> Self;
 }
 
+#[derive(Clone)]
 pub struct GateStack {
     pub gatestack: Vec<Gates>,
+    pub levels: Vec<u32>,
+    pub order: Vec<usize>,
+    // the gates driving each bus, a net with more than one driver
+    pub buses: HashMap<u32,Vec<usize>>,
+}
+
+impl GateStack {
+    pub fn new() -> Self {
+        GateStack {
+            gatestack: vec![],
+            levels: vec![],
+            order: vec![],
+            buses: HashMap::new(),
+        }
+    }
+}
+
+impl Gates {
+    pub fn name(&self) -> &str {
+        match self {
+            Gates::AND(_) => "AND",
+            Gates::OR(_) => "OR",
+            Gates::NAND(_) => "NAND",
+            Gates::NOR(_) => "NOR",
+            Gates::XOR(_) => "XOR",
+            Gates::XNOR(_) => "XNOR",
+            Gates::INV(_) => "INV",
+            Gates::BUF(_) => "BUF",
+            Gates::TRIBUF(_) => "TRIBUF",
+            Gates::CELL(gate) => &gate.cell.name,
+        }
+    }
+
+    pub fn net_inputs(&self) -> Vec<u32> {
+        match self {
+            Gates::AND(gate) => gate.net_in.clone(),
+            Gates::OR(gate) => gate.net_in.clone(),
+            Gates::NAND(gate) => gate.net_in.clone(),
+            Gates::NOR(gate) => gate.net_in.clone(),
+            Gates::XOR(gate) => gate.net_in.clone(),
+            Gates::XNOR(gate) => gate.net_in.clone(),
+            Gates::INV(gate) => vec![gate.net_in_a],
+            Gates::BUF(gate) => vec![gate.net_in_a],
+            Gates::TRIBUF(gate) => gate.net_in.clone(),
+            Gates::CELL(gate) => gate.net_in.clone(),
+        }
+    }
+
+    pub fn net_output(&self) -> u32 {
+        match self {
+            Gates::AND(gate) => gate.net_out,
+            Gates::OR(gate) => gate.net_out,
+            Gates::NAND(gate) => gate.net_out,
+            Gates::NOR(gate) => gate.net_out,
+            Gates::XOR(gate) => gate.net_out,
+            Gates::XNOR(gate) => gate.net_out,
+            Gates::INV(gate) => gate.net_out,
+            Gates::BUF(gate) => gate.net_out,
+            Gates::TRIBUF(gate) => gate.net_out,
+            Gates::CELL(gate) => gate.net_out,
+        }
+    }
+
+    pub fn output(&self) -> FiveLogic {
+        match self {
+            Gates::AND(gate) => gate.output,
+            Gates::OR(gate) => gate.output,
+            Gates::NAND(gate) => gate.output,
+            Gates::NOR(gate) => gate.output,
+            Gates::XOR(gate) => gate.output,
+            Gates::XNOR(gate) => gate.output,
+            Gates::INV(gate) => gate.output,
+            Gates::BUF(gate) => gate.output,
+            Gates::TRIBUF(gate) => gate.output,
+            Gates::CELL(gate) => gate.output,
+        }
+    }
+
+    // The value on one input pin, and a way to override it after load() so a
+    // fault can be placed on the pin.
+    pub fn input(&self, pin: usize) -> FiveLogic {
+        match self {
+            Gates::AND(gate) => gate.inputs[pin],
+            Gates::OR(gate) => gate.inputs[pin],
+            Gates::NAND(gate) => gate.inputs[pin],
+            Gates::NOR(gate) => gate.inputs[pin],
+            Gates::XOR(gate) => gate.inputs[pin],
+            Gates::XNOR(gate) => gate.inputs[pin],
+            Gates::INV(gate) => gate.input_a,
+            Gates::BUF(gate) => gate.input_a,
+            Gates::TRIBUF(gate) => gate.inputs[pin],
+            Gates::CELL(gate) => gate.inputs[pin],
+        }
+    }
+
+    pub fn setinput(&mut self, pin: usize, value: FiveLogic) {
+        match self {
+            Gates::AND(gate) => gate.inputs[pin] = value,
+            Gates::OR(gate) => gate.inputs[pin] = value,
+            Gates::NAND(gate) => gate.inputs[pin] = value,
+            Gates::NOR(gate) => gate.inputs[pin] = value,
+            Gates::XOR(gate) => gate.inputs[pin] = value,
+            Gates::XNOR(gate) => gate.inputs[pin] = value,
+            Gates::INV(gate) => gate.input_a = value,
+            Gates::BUF(gate) => gate.input_a = value,
+            Gates::TRIBUF(gate) => gate.inputs[pin] = value,
+            Gates::CELL(gate) => gate.inputs[pin] = value,
+        }
+    }
+
+    // Stores the values a gate was last evaluated with and the output it gave.
+    pub fn setpins(&mut self, inputs: &[FiveLogic], output: FiveLogic) {
+        match self {
+            Gates::AND(gate) => (gate.inputs, gate.output) = (inputs.to_vec(), output),
+            Gates::OR(gate) => (gate.inputs, gate.output) = (inputs.to_vec(), output),
+            Gates::NAND(gate) => (gate.inputs, gate.output) = (inputs.to_vec(), output),
+            Gates::NOR(gate) => (gate.inputs, gate.output) = (inputs.to_vec(), output),
+            Gates::XOR(gate) => (gate.inputs, gate.output) = (inputs.to_vec(), output),
+            Gates::XNOR(gate) => (gate.inputs, gate.output) = (inputs.to_vec(), output),
+            Gates::INV(gate) => (gate.input_a, gate.output) = (inputs[0], output),
+            Gates::BUF(gate) => (gate.input_a, gate.output) = (inputs[0], output),
+            Gates::TRIBUF(gate) => (gate.inputs, gate.output) = (inputs.to_vec(), output),
+            Gates::CELL(gate) => (gate.inputs, gate.output) = (inputs.to_vec(), output),
+        }
+    }
+}
+
+impl Default for GateStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Two drivers of one bus. A driver at Z gives way to the other, and two that
+// disagree are in contention, which leaves the bus X.
+pub fn resolve(a: FiveLogic, b: FiveLogic) -> FiveLogic {
+    match (a, b) {
+        (FiveLogic::Z, v) | (v, FiveLogic::Z) => v,
+        _ if a == b => a,
+        _ => FiveLogic::X,
+    }
+}
+
+// The good and faulty machines a five-valued signal stands for: D is 1 in the
+// good circuit and 0 in the faulty one. A floating Z reads as X in both.
+pub fn machines(value: FiveLogic) -> (ThreeLogic, ThreeLogic) {
+    match value {
+        FiveLogic::ZERO => (ThreeLogic::ZERO, ThreeLogic::ZERO),
+        FiveLogic::ONE => (ThreeLogic::ONE, ThreeLogic::ONE),
+        FiveLogic::D => (ThreeLogic::ONE, ThreeLogic::ZERO),
+        FiveLogic::Dnot => (ThreeLogic::ZERO, ThreeLogic::ONE),
+        FiveLogic::X | FiveLogic::Z => (ThreeLogic::X, ThreeLogic::X),
+    }
+}
+
+// Puts the two machines back together. The D-calculus has no value for one
+// machine known and the other not, so that is X.
+pub fn combine(good: ThreeLogic, faulty: ThreeLogic) -> FiveLogic {
+    match (good, faulty) {
+        (ThreeLogic::ZERO, ThreeLogic::ZERO) => FiveLogic::ZERO,
+        (ThreeLogic::ONE, ThreeLogic::ONE) => FiveLogic::ONE,
+        (ThreeLogic::ONE, ThreeLogic::ZERO) => FiveLogic::D,
+        (ThreeLogic::ZERO, ThreeLogic::ONE) => FiveLogic::Dnot,
+        _ => FiveLogic::X,
+    }
+}
+
+// Two input operators over the five-valued algebra, applied to the good and
+// faulty machines separately. So D AND Dnot is 0 and D XOR D is 0.
+fn bitwise(a: FiveLogic, b: FiveLogic, op: fn(ThreeLogic, ThreeLogic) -> ThreeLogic) -> FiveLogic {
+    let ((a1, a0), (b1, b0)) = (machines(a), machines(b));
+    combine(op(a1, b1), op(a0, b0))
+}
+
+pub fn and(a: FiveLogic, b: FiveLogic) -> FiveLogic {
+    bitwise(a, b, ThreeLogic::and)
+}
+
+pub fn or(a: FiveLogic, b: FiveLogic) -> FiveLogic {
+    bitwise(a, b, ThreeLogic::or)
+}
+
+pub fn xor(a: FiveLogic, b: FiveLogic) -> FiveLogic {
+    bitwise(a, b, ThreeLogic::xor)
+}
+
+impl Gate for ANDGate{
+    fn evaluate<L: Logic>(&self, inputs: &[L]) -> L {
+        inputs.iter().fold(L::ONE, |acc, v| acc.and(*v))
+    }
+
+    fn eval(&mut self) {
+        self.output = self.evaluate(&self.inputs);
+    }
+}
+
+impl Gate for NANDGate{
+    fn evaluate<L: Logic>(&self, inputs: &[L]) -> L {
+        inputs.iter().fold(L::ONE, |acc, v| acc.and(*v)).not()
+    }
+
+    fn eval(&mut self) {
+        self.output = self.evaluate(&self.inputs);
+    }
+}
+
+impl Gate for ORGate{
+    fn evaluate<L: Logic>(&self, inputs: &[L]) -> L {
+        inputs.iter().fold(L::ZERO, |acc, v| acc.or(*v))
+    }
+
+    fn eval(&mut self) {
+        self.output = self.evaluate(&self.inputs);
+    }
+}
+
+impl Gate for NORGate{
+    fn evaluate<L: Logic>(&self, inputs: &[L]) -> L {
+        inputs.iter().fold(L::ZERO, |acc, v| acc.or(*v)).not()
+    }
+
+    fn eval(&mut self) {
+        self.output = self.evaluate(&self.inputs);
+    }
+}
+
+impl Gate for XORGate{
+    fn evaluate<L: Logic>(&self, inputs: &[L]) -> L {
+        inputs.iter().fold(L::ZERO, |acc, v| acc.xor(*v))
+    }
+
+    fn eval(&mut self) {
+        self.output = self.evaluate(&self.inputs);
+    }
+}
+
+impl Gate for XNORGate{
+    fn evaluate<L: Logic>(&self, inputs: &[L]) -> L {
+        inputs.iter().fold(L::ZERO, |acc, v| acc.xor(*v)).not()
+    }
+
+    fn eval(&mut self) {
+        self.output = self.evaluate(&self.inputs);
+    }
+}
+
+impl Gate for CellGate{
+    fn evaluate<L: Logic>(&self, inputs: &[L]) -> L {
+        self.cell.function.eval(inputs)
+    }
+
+    fn eval(&mut self) {
+        self.output = self.evaluate(&self.inputs);
+    }
+}
+
+impl Gate for NOTGate{
+    fn evaluate<L: Logic>(&self, inputs: &[L]) -> L {
+        inputs.first().copied().unwrap_or(L::UNKNOWN).not()
+    }
+
+    fn eval(&mut self) {
+        self.output = self.evaluate(&[self.input_a]);
+    }
+}
+
+impl Gate for BUFGate{
+    fn evaluate<L: Logic>(&self, inputs: &[L]) -> L {
+        inputs.first().copied().unwrap_or(L::UNKNOWN).strong()
+    }
+
+    fn eval(&mut self) {
+        self.output = self.evaluate(&[self.input_a]);
+    }
+}
+
+impl Gate for TRIBUFGate{
+    fn evaluate<L: Logic>(&self, inputs: &[L]) -> L {
+        let value = |k: usize| inputs.get(k).copied().unwrap_or(L::UNKNOWN).strong();
+
+        match value(1) {
+            enable if enable == L::ONE => value(0),
+            enable if enable == L::ZERO => L::FLOAT,
+            enable if enable == L::UNINITIALIZED => enable,
+            _ => L::UNKNOWN,
+        }
+    }
+
+    fn eval(&mut self) {
+        self.output = self.evaluate(&self.inputs);
+    }
+}
+
+impl Gate for Gates{
+    fn evaluate<L: Logic>(&self, inputs: &[L]) -> L {
+        match self {
+            Gates::AND(gate) => gate.evaluate(inputs),
+            Gates::OR(gate) => gate.evaluate(inputs),
+            Gates::NAND(gate) => gate.evaluate(inputs),
+            Gates::NOR(gate) => gate.evaluate(inputs),
+            Gates::XOR(gate) => gate.evaluate(inputs),
+            Gates::XNOR(gate) => gate.evaluate(inputs),
+            Gates::INV(gate) => gate.evaluate(inputs),
+            Gates::BUF(gate) => gate.evaluate(inputs),
+            Gates::TRIBUF(gate) => gate.evaluate(inputs),
+            Gates::CELL(gate) => gate.evaluate(inputs),
+        }
+    }
+
+    fn eval(&mut self) {
+        match self {
+            Gates::AND(gate) => gate.eval(),
+            Gates::OR(gate) => gate.eval(),
+            Gates::NAND(gate) => gate.eval(),
+            Gates::NOR(gate) => gate.eval(),
+            Gates::XOR(gate) => gate.eval(),
+            Gates::XNOR(gate) => gate.eval(),
+            Gates::INV(gate) => gate.eval(),
+            Gates::BUF(gate) => gate.eval(),
+            Gates::TRIBUF(gate) => gate.eval(),
+            Gates::CELL(gate) => gate.eval(),
+        }
+    }
+}
+
+
+// A storage element sitting outside the combinational gates. Its Q net acts
+// as an extra input to the logic and its D net, and reset net if it has one,
+// as extra outputs. All flops share one clock, and a reset at 1 loads a 0 on
+// the clock edge.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Flop {
+    pub d: u32,
+    pub q: u32,
+    pub init: FiveLogic,
+    #[serde(default)]
+    pub reset: Option<u32>,
+}
+
+pub struct Circuit {
+    pub gates: GateStack,
+    pub wires: HashMap<u32,Wire>,
+    pub inputs: Vec<u32>,
+    pub outputs: Vec<u32>,
+    pub flops: Vec<Flop>,
+    pub names: HashMap<u32,String>,
+    pub cells: HashMap<String,Cell>,
+}
+
+impl Circuit {
+    pub fn new() -> Self {
+        Circuit {
+            gates: GateStack::new(),
+            wires: HashMap::new(),
+            inputs: vec![],
+            outputs: vec![],
+            flops: vec![],
+            names: HashMap::new(),
+            cells: cells::library(),
+        }
+    }
+
+    pub fn netname(&self, net: u32) -> String {
+        netname(&self.names, net)
+    }
+
+    // Appends a gate of the given netlist keyword, or an instance of the cell
+    // of that name, and hooks it into the fanout of its input nets.
+    pub fn addgate(&mut self, gateop: &str, ins: &[u32], out: u32) {
+        let gatecount = self.gates.gatestack.len() as u32;
+
+        for net in ins {
+            add_fanout(&mut self.wires, *net, gatecount);
+        }
+        add_driven(&mut self.wires, out);
+
+        let gate = match self.cells.get(gateop) {
+            Some(cell) => Gates::CELL(CellGate {
+                cell: cell.clone(),
+                inputs: vec![FiveLogic::X; ins.len()],
+                output: FiveLogic::X,
+                net_in: ins.to_vec(),
+                net_out: out,
+            }),
+            None => makegate(gateop, ins, out),
+        };
+
+        self.gates.gatestack.push(gate);
+    }
+
+    // The number of inputs a gate keyword takes, or None if it takes any
+    // number.
+    pub fn arity(&self, gateop: &str) -> Option<usize> {
+        match gateop {
+            "INV" | "BUF" => Some(1),
+            "TRIBUF" => Some(2),
+            _ => self.cells.get(gateop).map(|c| c.inputs.len()),
+        }
+    }
+
+    pub fn addinput(&mut self, net: u32) {
+        add_driven(&mut self.wires, net);
+        if let Some(thiswire) = self.wires.get_mut(&net) {
+            thiswire.wiretype = WireType::PrimaryInput;
+        }
+        self.inputs.push(net);
+    }
+
+    pub fn addoutput(&mut self, net: u32) {
+        add_driven(&mut self.wires, net);
+        if let Some(thiswire) = self.wires.get_mut(&net) {
+            thiswire.wiretype = WireType::PrimaryOutput;
+        }
+        self.outputs.push(net);
+    }
+
+    pub fn addflop(&mut self, d: u32, q: u32, init: FiveLogic) {
+        add_driven(&mut self.wires, d);
+        add_driven(&mut self.wires, q);
+        if let Some(thiswire) = self.wires.get_mut(&q) {
+            thiswire.level = init;
+        }
+        self.flops.push(Flop { d, q, init, reset: None });
+    }
+
+    pub fn addresetflop(&mut self, d: u32, q: u32, init: FiveLogic, reset: u32) {
+        self.addflop(d, q, init);
+        add_driven(&mut self.wires, reset);
+        if let Some(flop) = self.flops.last_mut() {
+            flop.reset = Some(reset);
+        }
+    }
+}
+
+// The name of a net for reports and output files, or its number if it was
+// never given one.
+pub fn netname(names: &HashMap<u32,String>, net: u32) -> String {
+    match names.get(&net) {
+        Some(name) => name.clone(),
+        None => net.to_string(),
+    }
+}
+
+// Hands out net numbers for netlist formats that refer to nets by name.
+pub struct NetTable {
+    ids: HashMap<String,u32>,
+    start: u32,
+    next: u32,
+}
+
+impl NetTable {
+    pub fn new() -> Self {
+        Self::above(0)
+    }
+
+    // A table whose numbers start after `net`, for formats that mix named
+    // nets with numbered ones.
+    pub fn above(net: u32) -> Self {
+        NetTable {
+            ids: HashMap::new(),
+            start: net,
+            next: net,
+        }
+    }
+
+    pub fn id(&mut self, name: &str) -> u32 {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        let id = self.fresh();
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    // A net the file has no name for, such as one inside a gate the format
+    // reader breaks up.
+    pub fn fresh(&mut self) -> u32 {
+        self.next += 1;
+        self.next
+    }
+
+    // Every name handed out so far, keyed by net number. Nets from fresh() are
+    // named too, `_n` and their number kept clear of the file's names: written
+    // out by number they would read back as whatever net the file had given
+    // that number as its name.
+    pub fn names(&self) -> HashMap<u32,String> {
+        let mut names: HashMap<u32,String> = self.ids.iter().map(|(name, id)| (*id, name.clone())).collect();
+
+        for net in self.start + 1..=self.next {
+            names.entry(net).or_insert_with(|| {
+                let mut name = format!("_n{}", net);
+                while self.ids.contains_key(&name) {
+                    name.push('_');
+                }
+                name
+            });
+        }
+
+        names
+    }
+}
+
+impl Default for NetTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Circuit {
+    // Adds a gate with any number of inputs. A single input AND/OR/XOR becomes
+    // a BUF and a single input NAND/NOR/XNOR an INV. Cells must be given the
+    // number of inputs the library lists. Returns false for a keyword that has
+    // no gate or cell behind it.
+    pub fn addwidegate(&mut self, gateop: &str, ins: &[u32], out: u32) -> bool {
+        match gateop {
+            "INV" | "BUF" => self.addgate(gateop, ins, out),
+            "AND" | "OR" | "NAND" | "NOR" => {
+                if ins.len() == 1 {
+                    let op = if gateop == "AND" || gateop == "OR" { "BUF" } else { "INV" };
+                    self.addgate(op, ins, out);
+                    return true;
+                }
+
+                self.addgate(gateop, ins, out);
+            },
+            "XOR" | "XNOR" => {
+                if ins.len() == 1 {
+                    let op = if gateop == "XOR" { "BUF" } else { "INV" };
+                    self.addgate(op, ins, out);
+                    return true;
+                }
+
+                self.addgate(gateop, ins, out);
+            },
+            _ if self.arity(gateop) == Some(ins.len()) => self.addgate(gateop, ins, out),
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+impl Default for Circuit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParseReason {
+    Malformed,
+    UnreadableFile,
+    UnknownGate,
+    MissingOperand,
+    NonNumericNet,
+    InvalidName,
+    WrongOperandCount,
+    Unsupported,
+    InvalidDefinition,
+    Redefined,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+    pub reason: ParseReason,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.reason {
+            ParseReason::Malformed => "malformed line at",
+            ParseReason::UnreadableFile => "cannot read file",
+            ParseReason::UnknownGate => "unknown gate keyword",
+            ParseReason::MissingOperand => "missing operand after",
+            ParseReason::NonNumericNet => "net is not a number",
+            ParseReason::InvalidName => "invalid net name",
+            ParseReason::WrongOperandCount => "wrong number of operands at",
+            ParseReason::Unsupported => "unsupported construct",
+            ParseReason::InvalidDefinition => "invalid gate definition at",
+            ParseReason::Redefined => "gate type already defined",
+        };
+
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, reason)
+        } else {
+            write!(f, "{}:{}:{}: {} '{}'", self.file, self.line, self.column, reason, self.token)
+        }
+    }
+}
+
+pub fn parsegates(filename: &str) -> Result<Circuit, ParseError> {
+    match read_lines(filename) {
+        Ok(lines) => parselines(filename, lines.map_while(Result::ok)),
+        Err(_) => Err(ParseError {
+            file: filename.to_string(),
+            line: 0,
+            column: 0,
+            token: String::new(),
+            reason: ParseReason::UnreadableFile,
+        }),
+    }
+}
+
+// Splits a line into whitespace separated tokens along with the 1-based column
+// each one starts at.
+pub fn tokenize(line: &str) -> Vec<(usize,&str)> {
+    let mut tokens = vec![];
+    let mut start: Option<usize> = None;
+
+    for (i, c) in line.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                tokens.push((s + 1, &line[s..i]));
+                start = None;
+            },
+            (false, None) => start = Some(i),
+            _ => {},
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s + 1, &line[s..]));
+    }
+
+    tokens
 }
 
-impl Gate for ANDGate{
-    fn eval(&mut self) {
-        if self.input_a == FiveLogic::ZERO || self.input_b == FiveLogic::ZERO {
-            self.output = FiveLogic::ZERO;
-        } else if self.input_a == FiveLogic::X || self.input_b == FiveLogic::X {
-            self.output = FiveLogic::X;
-        } else if self.input_a == FiveLogic::ONE {
-            self.output = self.input_b;
-        } else if self.input_b == FiveLogic::ONE {
-            self.output = self.input_a; 
-        } else if self.input_a == FiveLogic::D && self.input_b == FiveLogic::D {
-            self.output = FiveLogic::D;
-        } else if self.input_a == FiveLogic::D && self.input_b == FiveLogic::Dnot {
-            self.output = FiveLogic::ZERO;
-        } else if self.input_a == FiveLogic::Dnot && self.input_b == FiveLogic::D {
-            self.output = FiveLogic::ZERO;
-        } else if self.input_a == FiveLogic::Dnot && self.input_b == FiveLogic::Dnot {
-            self.output = FiveLogic::Dnot;
-        }  
+// A net name is an identifier, optionally with a bit index: `a`, `carry_out`
+// or `bus[3]`.
+pub fn validname(name: &str) -> bool {
+    let (base, index) = match name.strip_suffix(']').and_then(|n| n.split_once('[')) {
+        Some((base, index)) => (base, Some(index)),
+        None => (name, None),
+    };
+
+    let identifier = base.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && base.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    match index {
+        Some(index) => identifier && !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()),
+        None => identifier,
+    }
+}
+
+// Netlist keywords, which cannot be used as the name of a defined gate.
+pub const KEYWORDS: [&str; 13] = ["AND", "OR", "NAND", "NOR", "XOR", "XNOR", "INV", "BUF", "TRIBUF", "DFF", "INPUT", "OUTPUT", "DEFGATE"];
+
+pub fn parselines<I>(filename: &str, lines: I) -> Result<Circuit, ParseError>
+where I: Iterator<Item = String>, {
+    let lines: Vec<String> = lines.collect();
+    let mut circuit = Circuit::new();
+
+    let error = |n: usize, (column, token): (usize, &str), reason: ParseReason| ParseError {
+        file: filename.to_string(),
+        line: n + 1,
+        column,
+        token: token.to_string(),
+        reason,
+    };
+
+    // gate definitions come first so gates can be used above them, and
+    // their tables are kept out of the net numbering
+    for (n, line) in lines.iter().enumerate() {
+        let tokens = tokenize(line);
+        let Some((keyword, rest)) = tokens.split_first() else {
+            continue;
+        };
+        if keyword.1 != "DEFGATE" {
+            continue;
+        }
+
+        let [name, count, body @ ..] = rest else {
+            let last = tokens[tokens.len() - 1];
+            return Err(error(n, (last.0 + last.1.len(), last.1), ParseReason::MissingOperand));
+        };
+        if body.is_empty() {
+            return Err(error(n, (count.0 + count.1.len(), count.1), ParseReason::MissingOperand));
+        }
+        if KEYWORDS.contains(&name.1) || circuit.cells.contains_key(name.1) {
+            return Err(error(n, *name, ParseReason::Redefined));
+        }
+        if !validname(name.1) || name.1.contains('[') {
+            return Err(error(n, *name, ParseReason::InvalidName));
+        }
+
+        let body: Vec<&str> = body.iter().map(|t| t.1).collect();
+        match cells::definegate(name.1, count.1, &body) {
+            Ok(cell) => {
+                circuit.cells.insert(cell.name.clone(), cell);
+            },
+            Err(bad) => {
+                let at = rest.iter().find(|t| t.1 == bad).copied().unwrap_or(*count);
+                return Err(error(n, at, ParseReason::InvalidDefinition));
+            },
+        }
+    }
+
+    // nets are either numbers or names. Named nets are numbered after the
+    // highest numbered net in the file and keep their names in the circuit's
+    // name table
+    let highest = lines.iter()
+        .filter(|l| !l.trim_start().starts_with("DEFGATE"))
+        .flat_map(|l| l.split_whitespace().skip(1))
+        .filter_map(|t| t.parse::<u32>().ok())
+        .max()
+        .unwrap_or(0);
+    let mut names = NetTable::above(highest);
+
+    for (n, line) in lines.iter().enumerate() {
+        let tokens = tokenize(line);
+        let error = |t: (usize, &str), reason: ParseReason| error(n, t, reason);
+
+        let mut net = |t: (usize, &str)| -> Result<u32, ParseError> {
+            match t.1.parse::<u32>() {
+                Ok(net) => Ok(net),
+                Err(_) if validname(t.1) => Ok(names.id(t.1)),
+                Err(_) => Err(error(t, ParseReason::InvalidName)),
+            }
+        };
+
+        let Some(&(column, gateop)) = tokens.first() else {
+            continue;
+        };
+
+        if gateop.starts_with('#') {
+            continue;
+        }
+
+        match gateop {
+            "DEFGATE" => {},
+            _ if matches!(gateop, "AND" | "OR" | "NAND" | "NOR" | "XOR" | "XNOR" | "INV" | "BUF" | "TRIBUF") || circuit.cells.contains_key(gateop) => {
+                // INV, BUF, TRIBUF and cells take a fixed number of inputs, the rest
+                // two or more, and the last net is always the output
+                let arity = circuit.arity(gateop);
+                let count = arity.unwrap_or(2) + 1;
+
+                if tokens.len() <= count {
+                    let last = tokens[tokens.len() - 1];
+                    return Err(error((last.0 + last.1.len(), last.1), ParseReason::MissingOperand));
+                }
+                if arity.is_some() && tokens.len() > count + 1 {
+                    return Err(error(tokens[count + 1], ParseReason::WrongOperandCount));
+                }
+
+                let mut nets: Vec<u32> = vec![];
+                for t in &tokens[1..] {
+                    nets.push(net(*t)?);
+                }
+
+                circuit.addgate(gateop, &nets[..nets.len() - 1], nets[nets.len() - 1]);
+            },
+            "DFF" => {
+                // DFF d q, or DFF d q reset
+                if tokens.len() < 3 {
+                    let last = tokens[tokens.len() - 1];
+                    return Err(error((last.0 + last.1.len(), last.1), ParseReason::MissingOperand));
+                }
+                if tokens.len() > 4 {
+                    return Err(error(tokens[4], ParseReason::WrongOperandCount));
+                }
+
+                let (d, q) = (net(tokens[1])?, net(tokens[2])?);
+                match tokens.get(3) {
+                    Some(reset) => {
+                        let reset = net(*reset)?;
+                        circuit.addresetflop(d, q, FiveLogic::X, reset);
+                    },
+                    None => circuit.addflop(d, q, FiveLogic::X),
+                }
+            },
+            "INPUT" | "OUTPUT" => {
+                for t in &tokens[1..] {
+                    if t.1 == "-1" {
+                        break;
+                    }
+                    let net = net(*t)?;
+
+                    if gateop == "INPUT" {
+                        circuit.addinput(net);
+                    } else {
+                        circuit.addoutput(net);
+                    }
+                }
+            },
+            _ => return Err(error((column, gateop), ParseReason::UnknownGate)),
+        }
+    }
+
+    circuit.names = names.names();
+
+    Ok(circuit)
+}
+
+fn makegate(gateop: &str, ins: &[u32], out: u32) -> Gates {
+    match gateop {
+        "AND" => Gates::AND(ANDGate {
+            net_in: ins.to_vec(),
+            net_out: out,
+            inputs: vec![FiveLogic::X; ins.len()],
+            output: FiveLogic::X,
+        }),
+        "OR" => Gates::OR(ORGate {
+            net_in: ins.to_vec(),
+            net_out: out,
+            inputs: vec![FiveLogic::X; ins.len()],
+            output: FiveLogic::X,
+        }),
+        "NAND" => Gates::NAND(NANDGate {
+            net_in: ins.to_vec(),
+            net_out: out,
+            inputs: vec![FiveLogic::X; ins.len()],
+            output: FiveLogic::X,
+        }),
+        "NOR" => Gates::NOR(NORGate {
+            net_in: ins.to_vec(),
+            net_out: out,
+            inputs: vec![FiveLogic::X; ins.len()],
+            output: FiveLogic::X,
+        }),
+        "XOR" => Gates::XOR(XORGate {
+            net_in: ins.to_vec(),
+            net_out: out,
+            inputs: vec![FiveLogic::X; ins.len()],
+            output: FiveLogic::X,
+        }),
+        "XNOR" => Gates::XNOR(XNORGate {
+            net_in: ins.to_vec(),
+            net_out: out,
+            inputs: vec![FiveLogic::X; ins.len()],
+            output: FiveLogic::X,
+        }),
+        "TRIBUF" => Gates::TRIBUF(TRIBUFGate {
+            net_in: ins.to_vec(),
+            net_out: out,
+            inputs: vec![FiveLogic::X; ins.len()],
+            output: FiveLogic::X,
+        }),
+        "INV" => Gates::INV(NOTGate {
+            net_in_a: ins[0],
+            net_out: out,
+            input_a: FiveLogic::X,
+            output: FiveLogic::X,
+        }),
+        _ => Gates::BUF(BUFGate {
+            net_in_a: ins[0],
+            net_out: out,
+            input_a: FiveLogic::X,
+            output: FiveLogic::X,
+        }),
+    }
+}
+
+fn add_fanout(wires: &mut HashMap<u32,Wire>, net: u32, gate: u32) {
+    wires.entry(net)
+        .or_insert(Wire{net, fanout: vec![], wiretype: WireType::Net, level: FiveLogic::X})
+        .fanout.push(gate);
+}
+
+fn add_driven(wires: &mut HashMap<u32,Wire>, net: u32) {
+    wires.entry(net)
+        .or_insert(Wire{net, fanout: vec![], wiretype: WireType::Net, level: FiveLogic::X});
+}
+
+pub fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
+where P: AsRef<Path>, {
+    let file: File = File::open(filename)?;
+    Ok(io::BufReader::new(file).lines())
+}
+
+// Assigns every gate a logic level (one more than the deepest gate driving
+// any of its inputs, primary inputs being level 0) and stores the gates in
+// level order so simulate() can walk them without recursion. Gates that are
+// never reached sit on a feedback loop and are appended after the rest.
+pub fn levelize(gates: &mut GateStack, wires: &HashMap<u32,Wire>) {
+    let (levels, order) = levelorder(gates, wires);
+
+    gates.levels = levels;
+    gates.order = order;
+
+    let mut drivers: HashMap<u32,Vec<usize>> = HashMap::new();
+    for (i, g) in gates.gatestack.iter().enumerate() {
+        drivers.entry(g.net_output()).or_default().push(i);
+    }
+    drivers.retain(|_, d| d.len() > 1);
+    gates.buses = drivers;
+}
+
+// The gate levels and the level order without storing them on the stack.
+pub fn levelorder(gates: &GateStack, wires: &HashMap<u32,Wire>) -> (Vec<u32>, Vec<usize>) {
+    let count = gates.gatestack.len();
+    let mut drivers: HashMap<u32,Vec<usize>> = HashMap::new();
+
+    for (i, g) in gates.gatestack.iter().enumerate() {
+        drivers.entry(g.net_output()).or_default().push(i);
+    }
+
+    let mut pending: Vec<usize> = vec![0; count];
+    for (i, g) in gates.gatestack.iter().enumerate() {
+        for net in g.net_inputs() {
+            if let Some(d) = drivers.get(&net) {
+                pending[i] += d.len();
+            }
+        }
+    }
+
+    let mut levels: Vec<u32> = vec![1; count];
+    let mut order: Vec<usize> = Vec::with_capacity(count);
+    let mut queue: VecDeque<usize> = (0..count).filter(|i| pending[*i] == 0).collect();
+
+    while let Some(i) = queue.pop_front() {
+        order.push(i);
+
+        if let Some(outnet) = wires.get(&gates.gatestack[i].net_output()) {
+            for f in &outnet.fanout {
+                let f = *f as usize;
+                levels[f] = levels[f].max(levels[i] + 1);
+                pending[f] -= 1;
+                if pending[f] == 0 {
+                    queue.push_back(f);
+                }
+            }
+        }
+    }
+
+    if order.len() < count {
+        let deepest = levels.iter().copied().max().unwrap_or(0);
+        for i in 0..count {
+            if pending[i] != 0 {
+                levels[i] = deepest + 1;
+                order.push(i);
+            }
+        }
+    }
+
+    order.sort_by_key(|i| (levels[*i], *i));
+
+    (levels, order)
+}
+
+// Where a simulation keeps its values. Five-valued simulation runs on the
+// wires and leaves each gate's pin values on the gate as well, which fault
+// simulation and pattern generation read back; any other value system keeps
+// them apart from the circuit, in logic::Values.
+pub trait Nets {
+    type Level: Logic;
+
+    // the circuit's wires, which give the fanout of every net
+    fn wires(&self) -> &HashMap<u32,Wire>;
+    fn level(&self, net: u32) -> Self::Level;
+    fn setlevel(&mut self, net: u32, level: Self::Level);
+    // what gate i put out when it was last evaluated
+    fn output(&self, gates: &GateStack, gate: usize) -> Self::Level;
+    // called with a gate's pin values each time it is evaluated
+    fn evaluated(&mut self, gate: &mut Gates, i: usize, inputs: &[Self::Level], output: Self::Level);
+}
+
+impl Nets for HashMap<u32,Wire> {
+    type Level = FiveLogic;
+
+    fn wires(&self) -> &HashMap<u32,Wire> {
+        self
+    }
+
+    fn level(&self, net: u32) -> FiveLogic {
+        self.get(&net).map(|w| w.level).unwrap_or(FiveLogic::X)
+    }
+
+    fn setlevel(&mut self, net: u32, level: FiveLogic) {
+        self.entry(net)
+            .or_insert(Wire{net, fanout: vec![], wiretype: WireType::Net, level: FiveLogic::X})
+            .level = level;
+    }
+
+    fn output(&self, gates: &GateStack, gate: usize) -> FiveLogic {
+        gates.gatestack[gate].output()
+    }
+
+    fn evaluated(&mut self, gate: &mut Gates, _: usize, inputs: &[FiveLogic], output: FiveLogic) {
+        gate.setpins(inputs, output);
+    }
+}
+
+// Evaluates gate i on the values of its input nets and returns the value its
+// output net settles to: the gate's own output, or on a bus the resolution of
+// the outputs of all its drivers. `pin` may change the value on any gate pin
+// first.
+fn evaluate<N: Nets, F>(gates: &mut GateStack, nets: &mut N, i: usize, pin: &F) -> N::Level
+where F: Fn(usize, Pin, N::Level) -> N::Level, {
+    let ins = gates.gatestack[i].net_inputs();
+    let inputs: Vec<N::Level> = ins.iter().enumerate().map(|(k, n)| pin(i, Pin::Input(k), nets.level(*n))).collect();
+    let output = gates.gatestack[i].evaluate(&inputs);
+    nets.evaluated(&mut gates.gatestack[i], i, &inputs, output);
+
+    let driven = |d: usize| pin(d, Pin::Output, nets.output(gates, d));
+    match gates.buses.get(&gates.gatestack[i].net_output()) {
+        Some(drivers) => drivers.iter().map(|d| driven(*d)).reduce(N::Level::resolve).unwrap_or(N::Level::FLOAT),
+        None => driven(i),
+    }
+}
+
+// Evaluates every gate once in level order. Each gate is recomputed from the
+// current net values regardless of its previous output, so running this again
+// after changing inputs gives fresh results.
+pub fn simulate<N: Nets>(gates: &mut GateStack, nets: &mut N) {
+    simulatepins(gates, nets, &|_, _, value| value);
+}
+
+// simulate() with `pin` able to change the value on any gate pin, which is
+// how fault simulation puts its fault in place.
+pub fn simulatepins<N: Nets, F>(gates: &mut GateStack, nets: &mut N, pin: &F)
+where F: Fn(usize, Pin, N::Level) -> N::Level, {
+    if gates.order.len() != gates.gatestack.len() {
+        levelize(gates, nets.wires());
+    }
+
+    for k in 0..gates.order.len() {
+        let i = gates.order[k];
+        let level = evaluate(gates, nets, i, pin);
+        nets.setlevel(gates.gatestack[i].net_output(), level);
+    }
+}
+
+// Applies a new input vector on top of the values left by the previous run and
+// re-evaluates only the gates that see a changed input. Events are processed
+// lowest level first so a gate with several changed inputs is evaluated once.
+// Returns the number of gate evaluations performed, or, when a feedback loop
+// keeps the values from settling, the output nets still changing.
+pub fn resimulate<N: Nets>(gates: &mut GateStack, nets: &mut N, inputs: &[u32], values: &[N::Level]) -> Result<usize, Vec<u32>> {
+    if gates.order.len() != gates.gatestack.len() {
+        levelize(gates, nets.wires());
+    }
+
+    let mut events: BTreeSet<(u32,usize)> = BTreeSet::new();
+    let schedule = |events: &mut BTreeSet<(u32,usize)>, levels: &[u32], nets: &N, net: u32| {
+        for f in nets.wires().get(&net).map(|w| &w.fanout[..]).unwrap_or(&[]) {
+            events.insert((levels[*f as usize], *f as usize));
+        }
+    };
+
+    for (ins, value) in inputs.iter().zip(values) {
+        if nets.level(*ins) != *value {
+            nets.setlevel(*ins, *value);
+            schedule(&mut events, &gates.levels, nets, *ins);
+        }
+    }
+
+    // a feedback loop that never settles would otherwise keep scheduling itself
+    let limit = 16 * gates.gatestack.len().max(1);
+    let mut evaluations = 0;
+
+    while let Some((_, i)) = events.pop_first() {
+        if evaluations == limit {
+            let pending = std::iter::once(i).chain(events.iter().map(|(_, g)| *g));
+            let mut nets: Vec<u32> = pending.map(|g| gates.gatestack[g].net_output()).collect();
+            nets.sort();
+            nets.dedup();
+            return Err(nets);
+        }
+        evaluations += 1;
+
+        let level = evaluate(gates, nets, i, &|_, _, value| value);
+        let netout = gates.gatestack[i].net_output();
+        if nets.level(netout) != level {
+            nets.setlevel(netout, level);
+            schedule(&mut events, &gates.levels, nets, netout);
+        }
+    }
+
+    Ok(evaluations)
+}
+
+pub fn tologic(value: Option<&u8>) -> FiveLogic {
+    match value {
+        Some(0) => FiveLogic::ZERO,
+        Some(1) => FiveLogic::ONE,
+        _ => FiveLogic::X,
+    }
+}
+
+// Reads a value written as 0, 1, D, d (Dnot) or X. Anything else is X.
+pub fn parselevel(text: &str) -> FiveLogic {
+    match text.trim() {
+        "0" => FiveLogic::ZERO,
+        "1" => FiveLogic::ONE,
+        "D" => FiveLogic::D,
+        "d" | "Dnot" => FiveLogic::Dnot,
+        "Z" | "z" => FiveLogic::Z,
+        _ => FiveLogic::X,
+    }
+}
+
+// The way a value is written in vectors and reports, the reverse of
+// parselevel().
+pub fn levelname(level: FiveLogic) -> &'static str {
+    match level {
+        FiveLogic::ONE => "1",
+        FiveLogic::ZERO => "0",
+        FiveLogic::X => "X",
+        FiveLogic::D => "D",
+        FiveLogic::Dnot => "d",
+        FiveLogic::Z => "Z",
+    }
+}
+
+fn printlevel(level: FiveLogic) {
+    print!("{} ", levelname(level));
+}
+
+// Reads one input vector per line. Values may be separated by commas or
+// whitespace or written back to back; anything other than 0 or 1 is X.
+pub fn readvectors(filename: &str) -> Vec<Vec<u8>> {
+    let mut vectors = vec![];
+
+    if let Ok(lines) = read_lines(filename) {
+        for line in lines.map_while(Result::ok) {
+            let vector: Vec<u8> = line.chars()
+                .filter(|c| !c.is_whitespace() && *c != ',')
+                .map(|c| match c {
+                    '0' => 0,
+                    '1' => 1,
+                    _ => 2,
+                })
+                .collect();
+
+            if !vector.is_empty() {
+                vectors.push(vector);
+            }
+        }
+    }
+
+    vectors
+}
+
+pub fn logic (gates: &mut GateStack, wires: &mut HashMap<u32, Wire>, inputs: Vec<u32>, outputs: Vec<u32>, names: &HashMap<u32,String>, inputvec: Vec<u8>) {
+    for (m, ins) in inputs.iter().enumerate() {
+        let wire = wires.entry(*ins).or_insert(Wire{net: *ins, fanout: vec![], wiretype: WireType::Net, level: FiveLogic::X});
+
+        wire.level = tologic(inputvec.get(m));
+    }
+
+    simulate(gates, wires);
+
+    println!();
+    println!("Circuit outputs:");
+
+    for o in &outputs {
+        print!("{} ",netname(names, *o));
+    }
+
+    println!();
+    println!();
+    println!("Output vector:");
+
+    for o in &outputs {
+        printlevel(wires.get(o).map(|w| w.level).unwrap_or(FiveLogic::X));
+
+        //println!("Net {:?} value is {:?}",*o,outnet.level);
+    }
+    println!();
+
+}
+
+// Runs a stream of input vectors, keeping the circuit state between them so
+// each vector only costs the gates affected by the inputs that changed.
+pub fn logicstream(gates: &mut GateStack, wires: &mut HashMap<u32, Wire>, inputs: Vec<u32>, outputs: Vec<u32>, names: &HashMap<u32,String>, inputvecs: Vec<Vec<u8>>) {
+    for ins in &inputs {
+        wires.entry(*ins).or_insert(Wire{net: *ins, fanout: vec![], wiretype: WireType::Net, level: FiveLogic::X}).level = FiveLogic::X;
+    }
+
+    simulate(gates, wires);
+
+    println!();
+    println!("Circuit outputs:");
+
+    for o in &outputs {
+        print!("{} ",netname(names, *o));
+    }
+
+    println!();
+    println!();
+    println!("Output vectors:");
+
+    for inputvec in &inputvecs {
+        let values: Vec<FiveLogic> = (0..inputs.len()).map(|m| tologic(inputvec.get(m))).collect();
+
+        if let Err(nets) = resimulate(gates, wires, &inputs, &values) {
+            let unsettled: Vec<String> = nets.iter().map(|n| netname(names, *n)).collect();
+            println!("Error, feedback does not settle on nets {}", unsettled.join(" "));
+        }
+
+        for o in &outputs {
+            printlevel(wires.get(o).map(|w| w.level).unwrap_or(FiveLogic::X));
+        }
+        println!();
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn netlist(text: &str) -> (GateStack, HashMap<u32,Wire>, Vec<u32>, Vec<u32>) {
+        let circuit = parselines("test", text.lines().map(String::from)).unwrap();
+        (circuit.gates, circuit.wires, circuit.inputs, circuit.outputs)
     }
-}
 
-impl Gate for NANDGate{
-    fn eval(&mut self) {
-        if self.input_a == FiveLogic::ZERO || self.input_b == FiveLogic::ZERO {
-            self.output = invert(&FiveLogic::ZERO);
-        } else if self.input_a == FiveLogic::X || self.input_b == FiveLogic::X {
-            self.output = invert(&FiveLogic::X);
-        } else if self.input_a == FiveLogic::ONE {
-            self.output = invert(&self.input_b);
-        } else if self.input_b == FiveLogic::ONE {
-            self.output = invert(&self.input_a); 
-        } else if self.input_a == FiveLogic::D && self.input_b == FiveLogic::D {
-            self.output = invert(&FiveLogic::D);
-        } else if self.input_a == FiveLogic::D && self.input_b == FiveLogic::Dnot {
-            self.output = invert(&FiveLogic::ZERO);
-        } else if self.input_a == FiveLogic::Dnot && self.input_b == FiveLogic::D {
-            self.output = invert(&FiveLogic::ZERO);
-        } else if self.input_a == FiveLogic::Dnot && self.input_b == FiveLogic::Dnot {
-            self.output = invert(&FiveLogic::Dnot);
-        }  
+    fn parseerror(text: &str) -> ParseError {
+        match parselines("test.txt", text.lines().map(String::from)) {
+            Ok(_) => panic!("netlist parsed"),
+            Err(e) => e,
+        }
     }
-}
 
-impl Gate for ORGate{
-    fn eval(&mut self) {
-        if self.input_a == FiveLogic::ONE || self.input_b == FiveLogic::ONE {
-            self.output = FiveLogic::ONE;
-        } else if self.input_a == FiveLogic::X || self.input_b == FiveLogic::X {
-            self.output = FiveLogic::X;
-        } else if self.input_a == FiveLogic::ZERO {
-            self.output = self.input_b;
-        } else if self.input_b == FiveLogic::ZERO {
-            self.output = self.input_a; 
-        } else if self.input_a == FiveLogic::D && self.input_b == FiveLogic::D {
-            self.output = FiveLogic::D;
-        } else if self.input_a == FiveLogic::D && self.input_b == FiveLogic::Dnot {
-            self.output = FiveLogic::ONE;
-        } else if self.input_a == FiveLogic::Dnot && self.input_b == FiveLogic::D {
-            self.output = FiveLogic::ONE;
-        } else if self.input_a == FiveLogic::Dnot && self.input_b == FiveLogic::Dnot {
-            self.output = FiveLogic::Dnot;
-        }  
-    }
-}
+    #[test]
+    fn parse_skips_blank_lines() {
+        let (gates, _, inputs, outputs) = netlist("# xor\nINV 1 3\n\nINV 2 4\nAND 1 4 5\nAND 2 3 6\nOR 5 6 7\n\nINPUT 1 2 -1\nOUTPUT 7 -1\n");
 
-impl Gate for NORGate{
-    fn eval(&mut self) {
-        if self.input_a == FiveLogic::ONE || self.input_b == FiveLogic::ONE {
-            self.output = invert(&FiveLogic::ONE);
-        } else if self.input_a == FiveLogic::X || self.input_b == FiveLogic::X {
-            self.output = invert(&FiveLogic::X);
-        } else if self.input_a == FiveLogic::ZERO {
-            self.output = invert(&self.input_b);
-        } else if self.input_b == FiveLogic::ZERO {
-            self.output = invert(&self.input_a); 
-        } else if self.input_a == FiveLogic::D && self.input_b == FiveLogic::D {
-            self.output = invert(&FiveLogic::D);
-        } else if self.input_a == FiveLogic::D && self.input_b == FiveLogic::Dnot {
-            self.output = invert(&FiveLogic::ONE);
-        } else if self.input_a == FiveLogic::Dnot && self.input_b == FiveLogic::D {
-            self.output = invert(&FiveLogic::ONE);
-        } else if self.input_a == FiveLogic::Dnot && self.input_b == FiveLogic::Dnot {
-            self.output = invert(&FiveLogic::Dnot);
-        }  
+        assert_eq!(gates.gatestack.len(), 5);
+        assert_eq!(inputs, vec![1, 2]);
+        assert_eq!(outputs, vec![7]);
     }
-}
 
-impl Gate for NOTGate{
-    fn eval(&mut self) {
-        self.output = invert(&self.input_a);
+    #[test]
+    fn parse_unknown_gate() {
+        let e = parseerror("INV 1 3\nAMD 1 3 4");
+
+        assert_eq!(e.reason, ParseReason::UnknownGate);
+        assert_eq!((e.line, e.column, e.token.as_str()), (2, 1, "AMD"));
+        assert_eq!(e.to_string(), "test.txt:2:1: unknown gate keyword 'AMD'");
     }
-}
 
-impl Gate for BUFGate{
-    fn eval(&mut self) {
-        self.output = self.input_a;
+    #[test]
+    fn parse_missing_operand() {
+        let e = parseerror("AND 1 2");
+
+        assert_eq!(e.reason, ParseReason::MissingOperand);
+        assert_eq!((e.line, e.column), (1, 8));
     }
-}
 
+    #[test]
+    fn parse_invalid_name() {
+        let e = parseerror("INV 1 3\nOR 1 3x 4");
 
-pub fn parsegates(filename: &str) -> (GateStack, HashMap<u32,Wire>, Vec<u32>,Vec<u32>) {
-    let mut gates = GateStack {gatestack: vec![]};
-    let mut instack: Vec<u32> = vec![];
-    let mut outstack: Vec<u32> = vec![];
-    let mut gatecount: u32 = 0;
-    let mut wires: HashMap<u32,Wire> = HashMap::new();
+        assert_eq!(e.reason, ParseReason::InvalidName);
+        assert_eq!((e.line, e.column, e.token.as_str()), (2, 6, "3x"));
 
-    if let Ok(lines) = read_lines(filename){
-        for line in lines {
-            if let Ok(gate) = line {
-                let mut token = gate.split_whitespace();
-                
-                let gatetype = token.next();
+        let e = parseerror("INV 1 3\nINPUT 1 b- -1");
 
-                match gatetype {
-                    None => {
-                        println!("Error, no gate type");
-                        return (gates, wires, instack, outstack)
-                    },
-                    Some(gateop) => {
-                        match gateop {
-                            "AND" | "OR" | "NAND" | "NOR" => {
-                                let in1 = FromStr::from_str(token.next().unwrap()).unwrap();
-                                let in2 = FromStr::from_str(token.next().unwrap()).unwrap();
-                                let out = FromStr::from_str(token.next().unwrap()).unwrap();
-
-                                if wires.contains_key(&in1) {
-                                    if let Some(thiswire) = wires.get_mut(&in1) {
-                                        thiswire.fanout.push(gatecount);
-                                    }
-                                }
-                                else {
-                                    let thiswire = Wire{net: in1, fanout: vec![gatecount], wiretype: WireType::Net, level: FiveLogic::X};
-
-                                    wires.insert(in1, thiswire);
-
-                                }
-
-                                if wires.contains_key(&in2) {
-                                    if let Some(thiswire) = wires.get_mut(&in2) {
-                                        thiswire.fanout.push(gatecount);
-                                    }
-                                }
-                                else {
-                                    let thiswire = Wire{net: in2, fanout: vec![gatecount], wiretype: WireType::Net, level: FiveLogic::X};
-
-                                    wires.insert(in2, thiswire);
-
-                                }
-
-                                if wires.contains_key(&out) {
-                                    if let Some(thiswire) = wires.get_mut(&out) {
-                                        thiswire.fanout.push(gatecount);
-                                    }
-                                }
-                                else {
-                                    let thiswire = Wire{net: out, fanout: vec![], wiretype: WireType::Net, level: FiveLogic::X};
-
-                                    wires.insert(out, thiswire);
-
-                                }
-
-                                gatecount += 1;
-
-                                match gateop {
-                                    "AND" => {
-                                        gates.gatestack.push(
-                                            Gates::AND(ANDGate {
-                                                net_in_a: in1,
-                                                net_in_b: in2,
-                                                net_out: out,
-                                                input_a: FiveLogic::X,
-                                                input_b: FiveLogic::X,
-                                                output: FiveLogic::X,
-                                            })
-                                        )
-                                    },
-                                    "OR" => {
-                                        gates.gatestack.push(
-                                            Gates::OR(ORGate {
-                                                net_in_a: in1,
-                                                net_in_b: in2,
-                                                net_out: out,
-                                                input_a: FiveLogic::X,
-                                                input_b: FiveLogic::X,
-                                                output: FiveLogic::X,
-                                            })
-                                        )
-                                    },
-                                    "NAND" => {
-                                        gates.gatestack.push(
-                                            Gates::NAND(NANDGate {
-                                                net_in_a: in1,
-                                                net_in_b: in2,
-                                                net_out: out,
-                                                input_a: FiveLogic::X,
-                                                input_b: FiveLogic::X,
-                                                output: FiveLogic::X,
-                                            })
-                                        )
-                                    },
-                                    "NOR" => {
-                                        gates.gatestack.push(
-                                            Gates::NOR(NORGate {
-                                                net_in_a: in1,
-                                                net_in_b: in2,
-                                                net_out: out,
-                                                input_a: FiveLogic::X,
-                                                input_b: FiveLogic::X,
-                                                output: FiveLogic::X,
-                                            })
-                                        )
-                                    },
-                                    _ => {},
-                                }
-                            
-                                //println!("{:?} with input nets {:?} and {:?}, output net {:?}",gateop,in1,in2,out);
-                            },
-                            "INV" | "BUF" => {
-                                let in1 = FromStr::from_str(token.next().unwrap()).unwrap();
-                                let out = FromStr::from_str(token.next().unwrap()).unwrap();
-                                
-                                if wires.contains_key(&in1) {
-                                    if let Some(thiswire) = wires.get_mut(&in1) {
-                                        thiswire.fanout.push(gatecount);
-                                    }
-                                }
-                                else {
-                                    let thiswire = Wire{net: in1, fanout: vec![gatecount], wiretype: WireType::Net, level: FiveLogic::X};
-
-                                    wires.insert(in1, thiswire);
-
-                                }
-
-                                if wires.contains_key(&out) {
-                                    if let Some(thiswire) = wires.get_mut(&out) {
-                                        thiswire.fanout.push(gatecount);
-                                    }
-                                }
-                                else {
-                                    let thiswire = Wire{net: out, fanout: vec![], wiretype: WireType::Net, level: FiveLogic::X};
-
-                                    wires.insert(out, thiswire);
-
-                                }
-
-                                gatecount += 1;
-
-                                match gateop {
-                                    "INV" => {
-                                        gates.gatestack.push(
-                                            Gates::INV(NOTGate {
-                                                net_in_a: in1,
-                                                net_out: out,
-                                                input_a: FiveLogic::X,
-                                                output: FiveLogic::X,
-                                            })
-                                        )
-                                    },
-                                    "BUF" => {
-                                        gates.gatestack.push(
-                                            Gates::BUF(BUFGate {
-                                                net_in_a: in1,
-                                                net_out: out,
-                                                input_a: FiveLogic::X,
-                                                output: FiveLogic::X,
-                                            })
-                                        )
-                                    },
-                                    _ => {},
-                                }
-
-                                //println!("{:?} with input net {:?}, output net {:?}",gateop,in1,out);
-                            },
-                            "INPUT" => {
-                                for i in token {
-                                    let input = i.parse::<i32>().unwrap();
-                                    
-                                    let wirenum = input as u32;
-
-                                    if let Some(thiswire) = wires.get_mut(&wirenum) {
-                                        thiswire.wiretype = WireType::PrimaryInput;
-                                    }
-                                    
-                                    if input != -1 {
-                                        instack.push(input as u32);
-                                    }
-                                }
-                            },
-                            "OUTPUT" => {
-                                for i in token {
-                                    let output = i.parse::<i32>().unwrap();
-
-                                    let wirenum = output as u32;
-
-                                    if let Some(thiswire) = wires.get_mut(&wirenum) {
-                                        thiswire.wiretype = WireType::PrimaryOutput;
-                                    }
-
-                                    if output != -1 {
-                                        outstack.push(output as u32);
-                                    }
-                                }
-                            },
-                            _ => {
-                                println!("Error, invalid gate entry");
-                                return (gates, wires, instack, outstack)
-                            }
-                        }
-                    },
-                }
-            }
-        }
-    }
+        assert_eq!(e.reason, ParseReason::InvalidName);
+        assert_eq!((e.line, e.column, e.token.as_str()), (2, 9, "b-"));
 
-    (gates, wires, instack, outstack)
-}
+        let e = parseerror("INV bus[x] 3");
 
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where P: AsRef<Path>, {
-    let file: File = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
-}
+        assert_eq!(e.reason, ParseReason::InvalidName);
+    }
 
-fn evalline(currentwire: u32, gates: &mut GateStack, wires: &mut HashMap<u32,Wire>) {
-    
-    let line = wires.entry(currentwire)
-                                .or_insert(Wire{net: currentwire, fanout: vec![], wiretype: WireType::Net, level: FiveLogic::X});
+    #[test]
+    fn parse_named_nets() {
+        let circuit = parselines("test", "INV a 3\nAND 3 bus[1] carry_out\nINPUT a bus[1] -1\nOUTPUT carry_out -1".lines().map(String::from)).unwrap();
 
-    let fanout = line.fanout.clone();
+        assert_eq!(circuit.inputs, vec![4, 5]);
+        assert_eq!(circuit.outputs, vec![6]);
+        assert_eq!(circuit.netname(5), "bus[1]");
+        assert_eq!(circuit.netname(6), "carry_out");
+        assert_eq!(circuit.netname(3), "3");
+    }
+
+    #[test]
+    fn parse_wrong_operand_count() {
+        let e = parseerror("INV 1 2 3");
 
-    for i in fanout {
-        let gatetype = gates.gatestack.get_mut(i as usize).unwrap();
+        assert_eq!(e.reason, ParseReason::WrongOperandCount);
+        assert_eq!((e.line, e.column, e.token.as_str()), (1, 9, "3"));
+    }
 
-        match gatetype {
-            Gates::AND(ref mut gate) => {
-                let neta = gate.net_in_a;
-                let netb = gate.net_in_b;
-                let netout = gate.net_out;
-                let output = gate.output;
+    #[test]
+    fn parse_xor() {
+        let (mut gates, mut wires, inputs, _) = netlist("XOR 1 2 3 4\nXNOR 1 2 5\nINPUT 1 2 3 -1\nOUTPUT 4 5 -1");
 
-                gate.input_a = wires.get(&neta).unwrap().level;
-                gate.input_b = wires.get(&netb).unwrap().level;
+        assert_eq!((gates.gatestack[0].name(), gates.gatestack[1].name()), ("XOR", "XNOR"));
 
-                if gate.input_a != FiveLogic::X && gate.input_b != FiveLogic::X && output == FiveLogic::X {                   
-                    
-                    gate.eval();
+        apply(&mut wires, &inputs, &[FiveLogic::D, FiveLogic::ONE, FiveLogic::ONE]);
+        simulate(&mut gates, &mut wires);
+        assert_eq!((wires[&4].level, wires[&5].level), (FiveLogic::D, FiveLogic::D));
+    }
 
-                    let outnet = wires.entry(netout)
-                                        .or_insert(Wire{net: gate.net_in_a, fanout: vec![], wiretype: WireType::Net, level: gate.output});
+    #[test]
+    fn parse_cells() {
+        let (mut gates, mut wires, inputs, _) = netlist("MUX2 1 2 3 4\nOAI22 1 2 3 4 5\nINPUT 1 2 3 -1\nOUTPUT 5 -1");
 
-                    outnet.level = gate.output;
+        assert_eq!((gates.gatestack[0].name(), gates.gatestack[1].name()), ("MUX2", "OAI22"));
+        assert_eq!(gates.gatestack[1].net_inputs(), vec![1, 2, 3, 4]);
 
-                    evalline(outnet.net, gates, wires);
+        apply(&mut wires, &inputs, &[FiveLogic::ZERO, FiveLogic::D, FiveLogic::ONE]);
+        simulate(&mut gates, &mut wires);
+        assert_eq!((wires[&4].level, wires[&5].level), (FiveLogic::D, FiveLogic::Dnot));
 
-                }     
-            },
-            Gates::NAND(ref mut gate) => {
-                let neta = gate.net_in_a;
-                let netb = gate.net_in_b;
-                let netout = gate.net_out;
-                let output = gate.output;
+        let e = parselines("test", ["MUX2 1 2 3".to_string()].into_iter()).err().unwrap();
+        assert_eq!(e.reason, ParseReason::MissingOperand);
+    }
 
-                gate.input_a = wires.get(&neta).unwrap().level;
-                gate.input_b = wires.get(&netb).unwrap().level;
+    #[test]
+    fn parse_dff() {
+        let circuit = parselines("test", "DFF 3 4\nDFF d q r\nAND 1 4 3\nINPUT 1 -1\nOUTPUT 3 -1".lines().map(String::from)).unwrap();
 
-                if gate.input_a != FiveLogic::X && gate.input_b != FiveLogic::X && output == FiveLogic::X {                   
-                    
-                    gate.eval();
+        assert_eq!(circuit.flops.len(), 2);
+        assert_eq!((circuit.flops[0].d, circuit.flops[0].q, circuit.flops[0].reset), (3, 4, None));
+        assert_eq!(circuit.netname(circuit.flops[1].reset.unwrap()), "r");
 
-                    let outnet = wires.entry(netout)
-                                        .or_insert(Wire{net: gate.net_in_a, fanout: vec![], wiretype: WireType::Net, level: gate.output});
+        assert_eq!(parseerror("DFF 1").reason, ParseReason::MissingOperand);
+        let e = parseerror("DFF 1 2 3 4");
+        assert_eq!((e.reason, e.token.as_str()), (ParseReason::WrongOperandCount, "4"));
+        assert_eq!(parseerror("DEFGATE DFF 1 10").reason, ParseReason::Redefined);
+    }
 
-                    outnet.level = gate.output;
+    #[test]
+    fn parse_defgate() {
+        let (mut gates, mut wires, inputs, _) = netlist("MAJ3 1 2 3 4\nDEFGATE MAJ3 3 00010111\nDEFGATE ANDNOT 2 10\nANDNOT 4 3 5\nINPUT 1 2 3 -1\nOUTPUT 5 -1");
 
-                    evalline(outnet.net, gates, wires);
-                }
-            },
-            Gates::OR(ref mut gate) => {
-                let neta = gate.net_in_a;
-                let netb = gate.net_in_b;
-                let netout = gate.net_out;
-                let output = gate.output;
+        assert_eq!((gates.gatestack[0].name(), gates.gatestack[1].name()), ("MAJ3", "ANDNOT"));
+        // the table bits are not mistaken for nets
+        assert_eq!(wires.len(), 5);
 
-                gate.input_a = wires.get(&neta).unwrap().level;
-                gate.input_b = wires.get(&netb).unwrap().level;
+        apply(&mut wires, &inputs, &[FiveLogic::ONE, FiveLogic::D, FiveLogic::X]);
+        simulate(&mut gates, &mut wires);
+        assert_eq!((wires[&4].level, wires[&5].level), (FiveLogic::X, FiveLogic::X));
 
-                if gate.input_a != FiveLogic::X && gate.input_b != FiveLogic::X && output == FiveLogic::X {                   
-                    
-                    gate.eval();
+        apply(&mut wires, &inputs, &[FiveLogic::ONE, FiveLogic::D, FiveLogic::ZERO]);
+        simulate(&mut gates, &mut wires);
+        assert_eq!((wires[&4].level, wires[&5].level), (FiveLogic::D, FiveLogic::D));
 
-                    let outnet = wires.entry(netout)
-                                        .or_insert(Wire{net: gate.net_in_a, fanout: vec![], wiretype: WireType::Net, level: gate.output});
+        let error = |text: &str| parselines("test", text.lines().map(String::from)).err().unwrap();
+        let e = error("DEFGATE MAJ3 3 0001011");
+        assert_eq!((e.column, e.token.as_str(), e.reason), (16, "0001011", ParseReason::InvalidDefinition));
+        let e = error("DEFGATE AND 2 0001");
+        assert_eq!((e.token.as_str(), e.reason), ("AND", ParseReason::Redefined));
+        let e = error("DEFGATE MUX2 3 00011011");
+        assert_eq!(e.reason, ParseReason::Redefined);
+        let e = error("DEFGATE F 2");
+        assert_eq!(e.reason, ParseReason::MissingOperand);
+    }
 
-                    outnet.level = gate.output;
+    #[test]
+    fn tristate_bus() {
+        let (mut gates, mut wires, inputs, _) = netlist("TRIBUF a ea bus\nTRIBUF b eb bus\nAND bus c y\nINPUT a b c ea eb -1\nOUTPUT bus y -1");
+        let bus = gates.gatestack[0].net_output();
+        let y = gates.gatestack[2].net_output();
+        let (zero, one, x, z) = (FiveLogic::ZERO, FiveLogic::ONE, FiveLogic::X, FiveLogic::Z);
+
+        let mut run = |values: &[FiveLogic]| {
+            apply(&mut wires, &inputs, values);
+            simulate(&mut gates, &mut wires);
+            (wires[&bus].level, wires[&y].level)
+        };
 
-                    evalline(outnet.net, gates, wires);
-                }
-            },
-            Gates::NOR(ref mut gate) => {
-                let neta = gate.net_in_a;
-                let netb = gate.net_in_b;
-                let netout = gate.net_out;
-                let output = gate.output;
+        // nothing driving, one driver, agreeing drivers and contention
+        assert_eq!(run(&[one, zero, one, zero, zero]), (z, x));
+        assert_eq!(run(&[FiveLogic::D, zero, one, one, zero]), (FiveLogic::D, FiveLogic::D));
+        assert_eq!(run(&[one, one, one, one, one]), (one, one));
+        assert_eq!(run(&[one, zero, one, one, one]), (x, x));
+        assert_eq!(run(&[one, zero, zero, x, zero]), (x, zero));
 
-                gate.input_a = wires.get(&neta).unwrap().level;
-                gate.input_b = wires.get(&netb).unwrap().level;
+        assert_eq!(resolve(FiveLogic::D, FiveLogic::Z), FiveLogic::D);
+        assert_eq!(resolve(FiveLogic::D, FiveLogic::ONE), FiveLogic::X);
+        assert_eq!(parselevel("Z"), z);
+    }
 
-                if gate.input_a != FiveLogic::X && gate.input_b != FiveLogic::X && output == FiveLogic::X {                   
-                    
-                    gate.eval();
+    #[test]
+    fn parse_wide_gates() {
+        let (mut gates, mut wires, inputs, _) = netlist("AND 1 2 3 4 9\nNOR 1 2 3 4 10\nINPUT 1 2 3 4 -1\nOUTPUT 9 10 -1");
 
-                    let outnet = wires.entry(netout)
-                                        .or_insert(Wire{net: gate.net_in_a, fanout: vec![], wiretype: WireType::Net, level: gate.output});
+        assert_eq!(gates.gatestack.len(), 2);
+        assert_eq!(gates.gatestack[0].net_inputs(), vec![1, 2, 3, 4]);
+        assert_eq!(gates.gatestack[0].net_output(), 9);
 
-                    outnet.level = gate.output;
+        apply(&mut wires, &inputs, &[FiveLogic::ONE, FiveLogic::D, FiveLogic::ONE, FiveLogic::D]);
+        simulate(&mut gates, &mut wires);
+        assert_eq!((wires[&9].level, wires[&10].level), (FiveLogic::D, FiveLogic::ZERO));
 
-                    evalline(outnet.net, gates, wires);
-                }
-            },
-            Gates::INV(ref mut gate) => {
-                let neta = gate.net_in_a;
-                let netout = gate.net_out;
-                let output = gate.output;
+        apply(&mut wires, &inputs, &[FiveLogic::X, FiveLogic::D, FiveLogic::ZERO, FiveLogic::Dnot]);
+        simulate(&mut gates, &mut wires);
+        assert_eq!((wires[&9].level, wires[&10].level), (FiveLogic::ZERO, FiveLogic::X));
 
-                gate.input_a = wires.get(&neta).unwrap().level;
-                
-                if gate.input_a != FiveLogic::X || output == FiveLogic::X {                   
-                    
-                    gate.eval();
+        apply(&mut wires, &inputs, &[FiveLogic::ZERO, FiveLogic::D, FiveLogic::ZERO, FiveLogic::Dnot]);
+        simulate(&mut gates, &mut wires);
+        assert_eq!((wires[&9].level, wires[&10].level), (FiveLogic::ZERO, FiveLogic::ZERO));
+    }
 
-                    let outnet = wires.entry(netout)
-                                        .or_insert(Wire{net: gate.net_in_a, fanout: vec![], wiretype: WireType::Net, level: gate.output});
+    fn apply(wires: &mut HashMap<u32,Wire>, inputs: &[u32], values: &[FiveLogic]) {
+        for (net, value) in inputs.iter().zip(values) {
+            wires.get_mut(net).unwrap().level = *value;
+        }
+    }
 
-                    outnet.level = gate.output;
+    #[test]
+    fn levelize_xor() {
+        let (mut gates, wires, _, _) = netlist("INV 1 3\nINV 2 4\nAND 1 4 5\nAND 2 3 6\nOR 5 6 7\nINPUT 1 2 -1\nOUTPUT 7 -1");
 
-                    evalline(outnet.net, gates, wires);
-                }
-            },
-            Gates::BUF(ref mut gate) => {
-                let neta = gate.net_in_a;
-                let netout = gate.net_out;
-                let output = gate.output;
+        levelize(&mut gates, &wires);
 
-                gate.input_a = wires.get(&neta).unwrap().level;
-                
-                if gate.input_a != FiveLogic::X || output == FiveLogic::X {                   
-                    
-                    gate.eval();
+        assert_eq!(gates.levels, vec![1, 1, 2, 2, 3]);
+        assert_eq!(gates.order, vec![0, 1, 2, 3, 4]);
+    }
 
-                    let outnet = wires.entry(netout)
-                                        .or_insert(Wire{net: gate.net_in_a, fanout: vec![], wiretype: WireType::Net, level: gate.output});
+    #[test]
+    fn levelize_out_of_order() {
+        let (mut gates, wires, _, _) = netlist("OR 5 6 7\nAND 1 4 5\nINV 2 4\nBUF 1 6\nINPUT 1 2 -1\nOUTPUT 7 -1");
 
-                    outnet.level = gate.output;
+        levelize(&mut gates, &wires);
 
-                    evalline(outnet.net, gates, wires);
-                }
-            },
-        }
+        assert_eq!(gates.levels, vec![3, 2, 1, 1]);
+        assert_eq!(gates.order, vec![2, 3, 1, 0]);
     }
-    
 
-}
+    #[test]
+    fn simulate_repeatable() {
+        let (mut gates, mut wires, inputs, _) = netlist("INV 1 3\nINV 2 4\nAND 1 4 5\nAND 2 3 6\nOR 5 6 7\nINPUT 1 2 -1\nOUTPUT 7 -1");
 
-pub fn logic (gates: &mut GateStack, wires: &mut HashMap<u32, Wire>, inputs: Vec<u32>, outputs: Vec<u32>, inputvec: Vec<u8>) {
-    let mut m: usize = 0;
+        apply(&mut wires, &inputs, &[FiveLogic::ONE, FiveLogic::ZERO]);
+        simulate(&mut gates, &mut wires);
+        assert_eq!(wires[&7].level, FiveLogic::ONE);
 
-    for ins in &inputs {
-        let wire = wires.entry(*ins).or_insert(Wire{net: *ins, fanout: vec![], wiretype: WireType::Net, level: FiveLogic::X});
+        apply(&mut wires, &inputs, &[FiveLogic::ONE, FiveLogic::ONE]);
+        simulate(&mut gates, &mut wires);
+        assert_eq!(wires[&7].level, FiveLogic::ZERO);
 
-        match inputvec[m] {
-            0 => wire.level = FiveLogic::ZERO,
-            1 => wire.level = FiveLogic::ONE,
-            _ => wire.level = FiveLogic::X, 
-        }
-        
-        m += 1;
+        apply(&mut wires, &inputs, &[FiveLogic::X, FiveLogic::ONE]);
+        simulate(&mut gates, &mut wires);
+        assert_eq!(wires[&7].level, FiveLogic::X);
     }
 
-    for i in &inputs {
-        evalline(*i, gates, wires);
+    #[test]
+    fn resimulate_matches_simulate() {
+        let text = "INV 1 3\nINV 2 4\nAND 1 4 5\nAND 2 3 6\nOR 5 6 7\nINPUT 1 2 -1\nOUTPUT 7 -1";
+        let (mut gates, mut wires, inputs, _) = netlist(text);
+        let (mut fullgates, mut fullwires, _, _) = netlist(text);
+
+        simulate(&mut gates, &mut wires);
+
+        let vectors = [
+            [FiveLogic::ZERO, FiveLogic::ZERO],
+            [FiveLogic::ONE, FiveLogic::ZERO],
+            [FiveLogic::ONE, FiveLogic::ONE],
+            [FiveLogic::X, FiveLogic::ONE],
+            [FiveLogic::ZERO, FiveLogic::ONE],
+        ];
 
-        let mut terminate = true;
+        for vector in &vectors {
+            resimulate(&mut gates, &mut wires, &inputs, vector).unwrap();
 
-        for outs in &outputs {
-            let outnet = wires.get(outs).unwrap();
-            if outnet.level == FiveLogic::X {
-                terminate = false;
+            apply(&mut fullwires, &inputs, vector);
+            simulate(&mut fullgates, &mut fullwires);
+
+            for net in 1..=7 {
+                assert_eq!(wires[&net].level, fullwires[&net].level);
             }
         }
+    }
 
-        if terminate {
-            break
-        }
+    #[test]
+    fn resimulate_only_changed() {
+        let (mut gates, mut wires, inputs, _) = netlist("INV 1 3\nINV 2 4\nAND 1 4 5\nAND 2 3 6\nOR 5 6 7\nINPUT 1 2 -1\nOUTPUT 7 -1");
 
-    }
+        simulate(&mut gates, &mut wires);
+        resimulate(&mut gates, &mut wires, &inputs, &[FiveLogic::ONE, FiveLogic::ZERO]).unwrap();
 
-    println!("");
-    println!("Circuit outputs:");
+        assert_eq!(resimulate(&mut gates, &mut wires, &inputs, &[FiveLogic::ONE, FiveLogic::ZERO]), Ok(0));
 
-    for o in &outputs {
-        print!("{} ",o);
+        // flipping net 2 touches INV 2 4, both ANDs and the OR, but not INV 1 3
+        assert_eq!(resimulate(&mut gates, &mut wires, &inputs, &[FiveLogic::ONE, FiveLogic::ONE]), Ok(4));
+        assert_eq!(wires[&7].level, FiveLogic::ZERO);
     }
 
-    println!("");
-    println!("");
-    println!("Output vector:");
+    #[test]
+    fn resimulate_oscillates() {
+        // a ring of three inverters once the NAND lets it run
+        let (mut gates, mut wires, inputs, _) = netlist("NAND 0 3 1\nINV 1 2\nINV 2 3\nINPUT 0 -1\nOUTPUT 3 -1");
 
-    for o in &outputs {
-        let outnet = wires.get(o).unwrap();
-
-        match outnet.level {
-            FiveLogic::ONE => print!("1 "),
-            FiveLogic::ZERO => print!("0 "),
-            FiveLogic::X => print!("X "),
-            FiveLogic::D => print!("D "),
-            FiveLogic::Dnot => print!("d "),
-        }
+        apply(&mut wires, &inputs, &[FiveLogic::ZERO]);
+        simulate(&mut gates, &mut wires);
+        assert_eq!(wires[&3].level, FiveLogic::ONE);
 
-        //println!("Net {:?} value is {:?}",*o,outnet.level);
+        assert_eq!(resimulate(&mut gates, &mut wires, &inputs, &[FiveLogic::ZERO]), Ok(0));
+        let nets = resimulate(&mut gates, &mut wires, &inputs, &[FiveLogic::ONE]).unwrap_err();
+        assert!(!nets.is_empty() && nets.iter().all(|n| [1, 2, 3].contains(n)), "{:?}", nets);
     }
-    println!("");
 
-}
+    #[test]
+    fn simulate_deep_chain() {
+        let mut text = String::new();
+        for n in 0..100000 {
+            text.push_str(&format!("INV {} {}\n", n, n + 1));
+        }
+        text.push_str("INPUT 0 -1\nOUTPUT 100000 -1");
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let (mut gates, mut wires, inputs, _) = netlist(&text);
+
+        apply(&mut wires, &inputs, &[FiveLogic::ONE]);
+        simulate(&mut gates, &mut wires);
+
+        assert_eq!(wires[&100000].level, FiveLogic::ONE);
+        assert_eq!(gates.levels[99999], 100000);
+    }
     
     #[test]
     fn and_0_b() {
         let mut gate = ANDGate {
-            input_a: FiveLogic::ZERO,
-            input_b: FiveLogic::ONE,
+            inputs: vec![FiveLogic::ZERO, FiveLogic::ONE],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -744,11 +1756,9 @@ mod tests {
     #[test]
     fn and_a_0() {
         let mut gate = ANDGate {
-            input_a: FiveLogic::ONE,
-            input_b: FiveLogic::ZERO,
+            inputs: vec![FiveLogic::ONE, FiveLogic::ZERO],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -761,45 +1771,39 @@ mod tests {
     #[test]
     fn and_1_b() {
         let mut gate = ANDGate {
-            input_a: FiveLogic::ONE,
-            input_b: FiveLogic::D,
+            inputs: vec![FiveLogic::ONE, FiveLogic::D],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
         gate.eval();
 
-        assert_eq!(gate.output,gate.input_b);
+        assert_eq!(gate.output,gate.inputs[1]);
         assert_ne!(gate.output,FiveLogic::X);
     }
 
     #[test]
     fn and_a_1() {
         let mut gate = ANDGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::ONE,
+            inputs: vec![FiveLogic::D, FiveLogic::ONE],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
         gate.eval();
 
-        assert_eq!(gate.output,gate.input_a);
+        assert_eq!(gate.output,gate.inputs[0]);
         assert_ne!(gate.output,FiveLogic::X);
     }    
 
     #[test]
     fn and_x_b() {
         let mut gate = ANDGate {
-            input_a: FiveLogic::X,
-            input_b: FiveLogic::ONE,
+            inputs: vec![FiveLogic::X, FiveLogic::ONE],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -812,11 +1816,9 @@ mod tests {
     #[test]
     fn and_x_0() {
         let mut gate = ANDGate {
-            input_a: FiveLogic::X,
-            input_b: FiveLogic::ZERO,
+            inputs: vec![FiveLogic::X, FiveLogic::ZERO],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -829,11 +1831,9 @@ mod tests {
     #[test]
     fn and_a_x() {
         let mut gate = ANDGate {
-            input_a: FiveLogic::ONE,
-            input_b: FiveLogic::X,
+            inputs: vec![FiveLogic::ONE, FiveLogic::X],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -846,11 +1846,9 @@ mod tests {
     #[test]
     fn and_0_x() {
         let mut gate = ANDGate {
-            input_a: FiveLogic::ZERO,
-            input_b: FiveLogic::X,
+            inputs: vec![FiveLogic::ZERO, FiveLogic::X],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -863,11 +1861,9 @@ mod tests {
     #[test]
     fn and_dnot_d() {
         let mut gate = ANDGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::Dnot,
+            inputs: vec![FiveLogic::D, FiveLogic::Dnot],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -880,11 +1876,9 @@ mod tests {
     #[test]
     fn and_dnot_dnot() {
         let mut gate = ANDGate {
-            input_a: FiveLogic::Dnot,
-            input_b: FiveLogic::Dnot,
+            inputs: vec![FiveLogic::Dnot, FiveLogic::Dnot],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -897,11 +1891,9 @@ mod tests {
     #[test]
     fn and_d_d() {
         let mut gate = ANDGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::D,
+            inputs: vec![FiveLogic::D, FiveLogic::D],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -914,11 +1906,9 @@ mod tests {
     #[test]
     fn and_d_dnot() {
         let mut gate = ANDGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::Dnot,
+            inputs: vec![FiveLogic::D, FiveLogic::Dnot],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -991,11 +1981,9 @@ mod tests {
     #[test]
     fn nand_0_b() {
         let mut gate = NANDGate {
-            input_a: FiveLogic::ZERO,
-            input_b: FiveLogic::ONE,
+            inputs: vec![FiveLogic::ZERO, FiveLogic::ONE],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1008,11 +1996,9 @@ mod tests {
     #[test]
     fn nand_a_0() {
         let mut gate = NANDGate {
-            input_a: FiveLogic::ONE,
-            input_b: FiveLogic::ZERO,
+            inputs: vec![FiveLogic::ONE, FiveLogic::ZERO],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1025,45 +2011,39 @@ mod tests {
     #[test]
     fn nand_1_b() {
         let mut gate = NANDGate {
-            input_a: FiveLogic::ONE,
-            input_b: FiveLogic::D,
+            inputs: vec![FiveLogic::ONE, FiveLogic::D],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
         gate.eval();
 
-        assert_eq!(gate.output,invert(&gate.input_b));
+        assert_eq!(gate.output,invert(&gate.inputs[1]));
         assert_ne!(gate.output,FiveLogic::X);
     }
 
     #[test]
     fn nand_a_1() {
         let mut gate = NANDGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::ONE,
+            inputs: vec![FiveLogic::D, FiveLogic::ONE],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
         gate.eval();
 
-        assert_eq!(gate.output,invert(&gate.input_a));
+        assert_eq!(gate.output,invert(&gate.inputs[0]));
         assert_ne!(gate.output,FiveLogic::X);
     }    
 
     #[test]
     fn nand_x_b() {
         let mut gate = NANDGate {
-            input_a: FiveLogic::X,
-            input_b: FiveLogic::ONE,
+            inputs: vec![FiveLogic::X, FiveLogic::ONE],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1076,11 +2056,9 @@ mod tests {
     #[test]
     fn nand_x_0() {
         let mut gate = NANDGate {
-            input_a: FiveLogic::X,
-            input_b: FiveLogic::ZERO,
+            inputs: vec![FiveLogic::X, FiveLogic::ZERO],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1093,11 +2071,9 @@ mod tests {
     #[test]
     fn nand_a_x() {
         let mut gate = NANDGate {
-            input_a: FiveLogic::ONE,
-            input_b: FiveLogic::X,
+            inputs: vec![FiveLogic::ONE, FiveLogic::X],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1110,11 +2086,9 @@ mod tests {
     #[test]
     fn nand_0_x() {
         let mut gate = NANDGate {
-            input_a: FiveLogic::ZERO,
-            input_b: FiveLogic::X,
+            inputs: vec![FiveLogic::ZERO, FiveLogic::X],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1127,11 +2101,9 @@ mod tests {
     #[test]
     fn nand_dnot_d() {
         let mut gate = NANDGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::Dnot,
+            inputs: vec![FiveLogic::D, FiveLogic::Dnot],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1144,11 +2116,9 @@ mod tests {
     #[test]
     fn nand_dnot_dnot() {
         let mut gate = NANDGate {
-            input_a: FiveLogic::Dnot,
-            input_b: FiveLogic::Dnot,
+            inputs: vec![FiveLogic::Dnot, FiveLogic::Dnot],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1161,11 +2131,9 @@ mod tests {
     #[test]
     fn nand_d_d() {
         let mut gate = NANDGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::D,
+            inputs: vec![FiveLogic::D, FiveLogic::D],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1178,11 +2146,9 @@ mod tests {
     #[test]
     fn nand_d_dnot() {
         let mut gate = NANDGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::Dnot,
+            inputs: vec![FiveLogic::D, FiveLogic::Dnot],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1195,45 +2161,39 @@ mod tests {
     #[test]
     fn or_0_b() {
         let mut gate = ORGate {
-            input_a: FiveLogic::ZERO,
-            input_b: FiveLogic::ONE,
+            inputs: vec![FiveLogic::ZERO, FiveLogic::ONE],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
         gate.eval();
 
-        assert_eq!(gate.output,gate.input_b);
+        assert_eq!(gate.output,gate.inputs[1]);
         assert_ne!(gate.output,FiveLogic::X);
     }
 
     #[test]
     fn or_a_0() {
         let mut gate = ORGate {
-            input_a: FiveLogic::ONE,
-            input_b: FiveLogic::ZERO,
+            inputs: vec![FiveLogic::ONE, FiveLogic::ZERO],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
         gate.eval();
 
-        assert_eq!(gate.output,gate.input_a);
+        assert_eq!(gate.output,gate.inputs[0]);
         assert_ne!(gate.output,FiveLogic::X);
     }
 
     #[test]
     fn or_1_b() {
         let mut gate = ORGate {
-            input_a: FiveLogic::ONE,
-            input_b: FiveLogic::D,
+            inputs: vec![FiveLogic::ONE, FiveLogic::D],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1246,11 +2206,9 @@ mod tests {
     #[test]
     fn or_a_1() {
         let mut gate = ORGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::ONE,
+            inputs: vec![FiveLogic::D, FiveLogic::ONE],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1263,11 +2221,9 @@ mod tests {
     #[test]
     fn or_x_b() {
         let mut gate = ORGate {
-            input_a: FiveLogic::X,
-            input_b: FiveLogic::ONE,
+            inputs: vec![FiveLogic::X, FiveLogic::ONE],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1280,11 +2236,9 @@ mod tests {
     #[test]
     fn or_x_0() {
         let mut gate = ORGate {
-            input_a: FiveLogic::X,
-            input_b: FiveLogic::ZERO,
+            inputs: vec![FiveLogic::X, FiveLogic::ZERO],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1297,11 +2251,9 @@ mod tests {
     #[test]
     fn or_a_x() {
         let mut gate = ORGate {
-            input_a: FiveLogic::ONE,
-            input_b: FiveLogic::X,
+            inputs: vec![FiveLogic::ONE, FiveLogic::X],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1314,11 +2266,9 @@ mod tests {
     #[test]
     fn or_0_x() {
         let mut gate = ORGate {
-            input_a: FiveLogic::ZERO,
-            input_b: FiveLogic::X,
+            inputs: vec![FiveLogic::ZERO, FiveLogic::X],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1331,11 +2281,9 @@ mod tests {
     #[test]
     fn or_dnot_d() {
         let mut gate = ORGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::Dnot,
+            inputs: vec![FiveLogic::D, FiveLogic::Dnot],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1348,11 +2296,9 @@ mod tests {
     #[test]
     fn or_dnot_dnot() {
         let mut gate = ORGate {
-            input_a: FiveLogic::Dnot,
-            input_b: FiveLogic::Dnot,
+            inputs: vec![FiveLogic::Dnot, FiveLogic::Dnot],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1365,11 +2311,9 @@ mod tests {
     #[test]
     fn or_d_d() {
         let mut gate = ORGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::D,
+            inputs: vec![FiveLogic::D, FiveLogic::D],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1382,11 +2326,9 @@ mod tests {
     #[test]
     fn or_d_dnot() {
         let mut gate = ORGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::Dnot,
+            inputs: vec![FiveLogic::D, FiveLogic::Dnot],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1399,45 +2341,39 @@ mod tests {
     #[test]
     fn nor_0_b() {
         let mut gate = NORGate {
-            input_a: FiveLogic::ZERO,
-            input_b: FiveLogic::ONE,
+            inputs: vec![FiveLogic::ZERO, FiveLogic::ONE],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
         gate.eval();
 
-        assert_eq!(gate.output,invert(&gate.input_b));
+        assert_eq!(gate.output,invert(&gate.inputs[1]));
         assert_ne!(gate.output,invert(&FiveLogic::X));
     }
 
     #[test]
     fn nor_a_0() {
         let mut gate = NORGate {
-            input_a: FiveLogic::ONE,
-            input_b: FiveLogic::ZERO,
+            inputs: vec![FiveLogic::ONE, FiveLogic::ZERO],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
         gate.eval();
 
-        assert_eq!(gate.output,invert(&gate.input_a));
+        assert_eq!(gate.output,invert(&gate.inputs[0]));
         assert_ne!(gate.output,invert(&FiveLogic::X));
     }
 
     #[test]
     fn nor_1_b() {
         let mut gate = NORGate {
-            input_a: FiveLogic::ONE,
-            input_b: FiveLogic::D,
+            inputs: vec![FiveLogic::ONE, FiveLogic::D],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1450,11 +2386,9 @@ mod tests {
     #[test]
     fn nor_a_1() {
         let mut gate = NORGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::ONE,
+            inputs: vec![FiveLogic::D, FiveLogic::ONE],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1467,11 +2401,9 @@ mod tests {
     #[test]
     fn nor_x_b() {
         let mut gate = NORGate {
-            input_a: FiveLogic::X,
-            input_b: FiveLogic::ONE,
+            inputs: vec![FiveLogic::X, FiveLogic::ONE],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1484,11 +2416,9 @@ mod tests {
     #[test]
     fn nor_x_0() {
         let mut gate = NORGate {
-            input_a: FiveLogic::X,
-            input_b: FiveLogic::ZERO,
+            inputs: vec![FiveLogic::X, FiveLogic::ZERO],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1501,11 +2431,9 @@ mod tests {
     #[test]
     fn nor_a_x() {
         let mut gate = NORGate {
-            input_a: FiveLogic::ONE,
-            input_b: FiveLogic::X,
+            inputs: vec![FiveLogic::ONE, FiveLogic::X],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1518,11 +2446,9 @@ mod tests {
     #[test]
     fn nor_0_x() {
         let mut gate = NORGate {
-            input_a: FiveLogic::ZERO,
-            input_b: FiveLogic::X,
+            inputs: vec![FiveLogic::ZERO, FiveLogic::X],
             output: FiveLogic::ONE,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1535,11 +2461,9 @@ mod tests {
     #[test]
     fn nor_dnot_d() {
         let mut gate = NORGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::Dnot,
+            inputs: vec![FiveLogic::D, FiveLogic::Dnot],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1552,11 +2476,9 @@ mod tests {
     #[test]
     fn nor_dnot_dnot() {
         let mut gate = NORGate {
-            input_a: FiveLogic::Dnot,
-            input_b: FiveLogic::Dnot,
+            inputs: vec![FiveLogic::Dnot, FiveLogic::Dnot],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1569,11 +2491,9 @@ mod tests {
     #[test]
     fn nor_d_d() {
         let mut gate = NORGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::D,
+            inputs: vec![FiveLogic::D, FiveLogic::D],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1586,11 +2506,9 @@ mod tests {
     #[test]
     fn nor_d_dnot() {
         let mut gate = NORGate {
-            input_a: FiveLogic::D,
-            input_b: FiveLogic::Dnot,
+            inputs: vec![FiveLogic::D, FiveLogic::Dnot],
             output: FiveLogic::X,
-            net_in_a: 0,
-            net_in_b: 0,
+            net_in: vec![0, 0],
             net_out: 0,
         };
         
@@ -1600,4 +2518,102 @@ mod tests {
         assert_ne!(gate.output,FiveLogic::X);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn xor_d_d() {
+        let mut gate = XORGate {
+            inputs: vec![FiveLogic::D, FiveLogic::D],
+            output: FiveLogic::X,
+            net_in: vec![0, 0],
+            net_out: 0,
+        };
+
+        gate.eval();
+
+        assert_eq!(gate.output,FiveLogic::ZERO);
+    }
+
+    #[test]
+    fn xor_d_dnot() {
+        let mut gate = XORGate {
+            inputs: vec![FiveLogic::D, FiveLogic::Dnot],
+            output: FiveLogic::X,
+            net_in: vec![0, 0],
+            net_out: 0,
+        };
+
+        gate.eval();
+
+        assert_eq!(gate.output,FiveLogic::ONE);
+    }
+
+    #[test]
+    fn xor_1_d() {
+        let mut gate = XORGate {
+            inputs: vec![FiveLogic::ONE, FiveLogic::D],
+            output: FiveLogic::X,
+            net_in: vec![0, 0],
+            net_out: 0,
+        };
+
+        gate.eval();
+
+        assert_eq!(gate.output,FiveLogic::Dnot);
+    }
+
+    #[test]
+    fn xor_0_dnot() {
+        let mut gate = XORGate {
+            inputs: vec![FiveLogic::ZERO, FiveLogic::Dnot],
+            output: FiveLogic::X,
+            net_in: vec![0, 0],
+            net_out: 0,
+        };
+
+        gate.eval();
+
+        assert_eq!(gate.output,FiveLogic::Dnot);
+    }
+
+    #[test]
+    fn xnor_d_dnot() {
+        let mut gate = XNORGate {
+            inputs: vec![FiveLogic::D, FiveLogic::Dnot],
+            output: FiveLogic::X,
+            net_in: vec![0, 0],
+            net_out: 0,
+        };
+
+        gate.eval();
+
+        assert_eq!(gate.output,FiveLogic::ZERO);
+    }
+
+    #[test]
+    fn xnor_d_d_d() {
+        let mut gate = XNORGate {
+            inputs: vec![FiveLogic::D, FiveLogic::D, FiveLogic::D],
+            output: FiveLogic::X,
+            net_in: vec![0, 0, 0],
+            net_out: 0,
+        };
+
+        gate.eval();
+
+        assert_eq!(gate.output,FiveLogic::Dnot);
+    }
+
+    #[test]
+    fn xor_x() {
+        let mut gate = XORGate {
+            inputs: vec![FiveLogic::D, FiveLogic::X],
+            output: FiveLogic::ZERO,
+            net_in: vec![0, 0],
+            net_out: 0,
+        };
+
+        gate.eval();
+
+        assert_eq!(gate.output,FiveLogic::X);
+    }
+
+}
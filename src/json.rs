@@ -0,0 +1,333 @@
+// JSON netlist format.
+//
+//     {
+//       "format": "logic-netlist",
+//       "version": 1,
+//       "inputs": [1, 2],
+//       "outputs": [7],
+//       "gates": [
+//         {"type": "INV", "inputs": [1], "output": 3},
+//         {"type": "AND", "inputs": [1, 4], "output": 5}
+//       ],
+//       "flops": [
+//         {"d": 7, "q": 8, "init": "0"}
+//       ],
+//       "names": {"1": "a", "7": "sum"},
+//       "cells": {"MAJ3": {"inputs": 3, "table": "00010111"}}
+//     }
+//
+// format   must be "logic-netlist".
+// version  schema version, currently 1.
+// inputs   primary input nets, in input vector order.
+// outputs  primary output nets, in output vector order.
+// gates    one object per gate: "type" is one of AND, OR, NAND, NOR, XOR,
+//          XNOR, INV, BUF, TRIBUF, a library cell such as MUX2 or TIE0, or a
+//          gate from "cells". "inputs" lists the input nets in pin order and
+//          "output" is the driven net. AND to XNOR take one or more inputs,
+//          INV and BUF one, TRIBUF two (data, then enable) and cells the
+//          number of pins they declare.
+// flops    optional. "d" and "q" nets, the power-up value "init", one of
+//          "0", "1" or "X" (default "X"), and an optional "reset" net.
+// names    optional map from net number to net name. Nets without a name are
+//          reported by number.
+// cells    optional gate types defined by the netlist rather than the cell
+//          library: the number of inputs and the truth table, as in a
+//          DEFGATE line.
+//
+// Nets are non-negative integers. Unknown fields are rejected.
+//
+// Syntax errors carry serde_json's line and column. Errors in a gate can only
+// be placed approximately: they point at the nth line with a "type" key, which
+// is the gate's own line for files laid out one key per line, as written here.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self,Write};
+
+use serde::{Deserialize,Serialize};
+
+use crate::cells;
+use crate::gates::{levelname, Circuit, FiveLogic, ParseError, ParseReason};
+
+pub const FORMAT: &str = "logic-netlist";
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonNetlist {
+    pub format: String,
+    pub version: u32,
+    pub inputs: Vec<u32>,
+    pub outputs: Vec<u32>,
+    pub gates: Vec<JsonGate>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flops: Vec<JsonFlop>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub names: BTreeMap<u32,String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cells: BTreeMap<String,JsonCell>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonGate {
+    #[serde(rename = "type")]
+    pub gatetype: String,
+    pub inputs: Vec<u32>,
+    pub output: u32,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonFlop {
+    pub d: u32,
+    pub q: u32,
+    #[serde(default = "unknown")]
+    pub init: FiveLogic,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reset: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonCell {
+    pub inputs: usize,
+    pub table: String,
+}
+
+fn unknown() -> FiveLogic {
+    FiveLogic::X
+}
+
+pub fn tojson(circuit: &Circuit) -> JsonNetlist {
+    let library = cells::library();
+
+    JsonNetlist {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        inputs: circuit.inputs.clone(),
+        outputs: circuit.outputs.clone(),
+        gates: circuit.gates.gatestack.iter().map(|g| JsonGate {
+            gatetype: g.name().to_string(),
+            inputs: g.net_inputs(),
+            output: g.net_output(),
+        }).collect(),
+        flops: circuit.flops.iter().map(|f| JsonFlop { d: f.d, q: f.q, init: f.init, reset: f.reset }).collect(),
+        names: circuit.names.iter().map(|(net, name)| (*net, name.clone())).collect(),
+        cells: circuit.cells.iter()
+            .filter(|(name, cell)| library.get(*name) != Some(cell))
+            .map(|(name, cell)| (name.clone(), JsonCell { inputs: cell.inputs.len(), table: cells::truthtable(cell) }))
+            .collect(),
+    }
+}
+
+pub fn writejson<W: Write>(circuit: &Circuit, out: &mut W) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut *out, &tojson(circuit))?;
+    writeln!(out)
+}
+
+pub fn parsejson(filename: &str) -> Result<Circuit, ParseError> {
+    match fs::read_to_string(filename) {
+        Ok(text) => parsejsontext(filename, &text),
+        Err(_) => Err(ParseError {
+            file: filename.to_string(),
+            line: 0,
+            column: 0,
+            token: String::new(),
+            reason: ParseReason::UnreadableFile,
+        }),
+    }
+}
+
+// Gate errors point at the line of the gate's "type" key, or the start of the
+// file if it cannot be found.
+pub fn parsejsontext(filename: &str, text: &str) -> Result<Circuit, ParseError> {
+    let netlist: JsonNetlist = match serde_json::from_str(text) {
+        Ok(netlist) => netlist,
+        Err(e) => return Err(ParseError {
+            file: filename.to_string(),
+            line: e.line(),
+            column: e.column(),
+            token: e.to_string(),
+            reason: ParseReason::Malformed,
+        }),
+    };
+
+    let error = |token: &str, reason: ParseReason, nth: usize| {
+        let line = text.lines()
+            .enumerate()
+            .filter(|(_, l)| l.split("\"type\"").skip(1).any(|rest| rest.trim_start().starts_with(':')))
+            .nth(nth)
+            .map(|(n, _)| n + 1)
+            .unwrap_or(1);
+
+        ParseError {
+            file: filename.to_string(),
+            line,
+            column: 1,
+            token: token.to_string(),
+            reason,
+        }
+    };
+
+    if netlist.format != FORMAT || netlist.version != VERSION {
+        return Err(ParseError {
+            file: filename.to_string(),
+            line: 1,
+            column: 1,
+            token: format!("{} {}", netlist.format, netlist.version),
+            reason: ParseReason::Unsupported,
+        });
+    }
+
+    let mut circuit = Circuit::new();
+
+    for (name, cell) in &netlist.cells {
+        match cells::definegate(name, &cell.inputs.to_string(), &[cell.table.as_str()]) {
+            Ok(cell) => {
+                circuit.cells.insert(name.clone(), cell);
+            },
+            Err(_) => return Err(ParseError {
+                file: filename.to_string(),
+                line: 1,
+                column: 1,
+                token: name.clone(),
+                reason: ParseReason::InvalidDefinition,
+            }),
+        }
+    }
+
+    for (n, gate) in netlist.gates.iter().enumerate() {
+        let op = gate.gatetype.as_str();
+
+        if gate.inputs.is_empty() && circuit.arity(op) != Some(0) {
+            return Err(error(op, ParseReason::MissingOperand, n));
+        }
+        match circuit.arity(op) {
+            Some(k) if gate.inputs.len() > k => return Err(error(op, ParseReason::WrongOperandCount, n)),
+            Some(k) if gate.inputs.len() < k => return Err(error(op, ParseReason::MissingOperand, n)),
+            _ => {},
+        }
+        if !circuit.addwidegate(op, &gate.inputs, gate.output) {
+            return Err(error(op, ParseReason::UnknownGate, n));
+        }
+    }
+
+    for net in &netlist.inputs {
+        circuit.addinput(*net);
+    }
+    for net in &netlist.outputs {
+        circuit.addoutput(*net);
+    }
+    for flop in &netlist.flops {
+        if !matches!(flop.init, FiveLogic::ZERO | FiveLogic::ONE | FiveLogic::X) {
+            return Err(ParseError {
+                file: filename.to_string(),
+                line: 1,
+                column: 1,
+                token: levelname(flop.init).to_string(),
+                reason: ParseReason::Malformed,
+            });
+        }
+        match flop.reset {
+            Some(reset) => circuit.addresetflop(flop.d, flop.q, flop.init, reset),
+            None => circuit.addflop(flop.d, flop.q, flop.init),
+        }
+    }
+    circuit.names = netlist.names.into_iter().collect();
+
+    Ok(circuit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::{parselines, Gates, ANDGate, Wire, WireType};
+
+    const XOR: &str = "INV 1 3\nINV 2 4\nAND 1 4 5\nAND 2 3 6\nOR 5 6 7\nINPUT 1 2 -1\nOUTPUT 7 -1";
+
+    #[test]
+    fn round_trip() {
+        let circuit = parselines("test", XOR.lines().map(String::from)).unwrap();
+
+        let mut text: Vec<u8> = vec![];
+        writejson(&circuit, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        let reread = parsejsontext("test.json", &text).unwrap();
+
+        assert_eq!(tojson(&reread), tojson(&circuit));
+        assert!(text.contains("\"type\": \"AND\",\n      \"inputs\": [\n        1,\n        4\n      ],\n      \"output\": 5"));
+    }
+
+    #[test]
+    fn defined_gates() {
+        let netlist = "DEFGATE MAJ3 3 11- 1-1 -11\nMAJ3 1 2 3 4\nMUX2 1 2 3 5\nINPUT 1 2 3 -1\nOUTPUT 4 5 -1";
+        let circuit = parselines("test", netlist.lines().map(String::from)).unwrap();
+
+        let json = tojson(&circuit);
+        assert_eq!(json.cells.len(), 1);
+        assert_eq!(json.cells["MAJ3"], JsonCell { inputs: 3, table: "00010111".to_string() });
+
+        let mut text: Vec<u8> = vec![];
+        writejson(&circuit, &mut text).unwrap();
+        let reread = parsejsontext("test.json", &String::from_utf8(text).unwrap()).unwrap();
+        assert_eq!(reread.cells["MAJ3"], circuit.cells["MAJ3"]);
+        assert_eq!(tojson(&reread), json);
+    }
+
+    #[test]
+    fn schema() {
+        let text = r#"{
+            "format": "logic-netlist",
+            "version": 1,
+            "inputs": [1, 2],
+            "outputs": [3],
+            "gates": [{"type": "NAND", "inputs": [1, 2], "output": 3}],
+            "flops": [{"d": 3, "q": 4}],
+            "names": {"1": "a"}
+        }"#;
+        let circuit = parsejsontext("test.json", text).unwrap();
+
+        assert_eq!(circuit.gates.gatestack[0].name(), "NAND");
+        assert_eq!(circuit.flops[0].init, FiveLogic::X);
+        assert_eq!(circuit.netname(1), "a");
+        assert_eq!(tojson(&circuit).names.get(&1).map(|n| n.as_str()), Some("a"));
+    }
+
+    #[test]
+    fn errors() {
+        let e = parsejsontext("t.json", "{\"format\": \"logic-netlist\", \"version\": 1,\n \"inputs\": [1], \"outputs\": [2], \"gates\": [], \"extra\": 0}").err().unwrap();
+        assert_eq!((e.line, e.reason), (2, ParseReason::Malformed));
+
+        let e = parsejsontext("t.json", "{\"format\": \"logic-netlist\", \"version\": 1, \"inputs\": [1], \"outputs\": [2],\n \"gates\": [\n{\"type\": \"INV\", \"inputs\": [1], \"output\": 3},\n{\"type\": \"MUX\", \"inputs\": [1, 3], \"output\": 2}]}").err().unwrap();
+        assert_eq!((e.line, e.token.as_str(), e.reason), (4, "MUX", ParseReason::UnknownGate));
+
+        // a net named "type" is not a gate
+        let e = parsejsontext("t.json", "{\"format\": \"logic-netlist\", \"version\": 1, \"inputs\": [1], \"outputs\": [2],\n \"names\": {\"1\": \"type\"},\n \"gates\": [\n{\"type\": \"MUX\", \"inputs\": [1], \"output\": 2}]}").err().unwrap();
+        assert_eq!((e.line, e.reason), (4, ParseReason::UnknownGate));
+
+        let e = parsejsontext("t.json", "{\"format\": \"logic-netlist\", \"version\": 1, \"inputs\": [1], \"outputs\": [2], \"gates\": [],\n \"flops\": [{\"d\": 2, \"q\": 1, \"init\": \"D\"}]}").err().unwrap();
+        assert_eq!((e.token.as_str(), e.reason), ("D", ParseReason::Malformed));
+
+        let e = parsejsontext("t.json", "{\"format\": \"logic-netlist\", \"version\": 2, \"inputs\": [], \"outputs\": [], \"gates\": []}").err().unwrap();
+        assert_eq!(e.reason, ParseReason::Unsupported);
+    }
+
+    #[test]
+    fn serde_types() {
+        let gate = Gates::AND(ANDGate {
+            inputs: vec![FiveLogic::D, FiveLogic::ONE],
+            output: FiveLogic::Dnot,
+            net_in: vec![1, 2],
+            net_out: 3,
+        });
+        let text = serde_json::to_string(&gate).unwrap();
+        assert_eq!(text, r#"{"type":"AND","inputs":["D","1"],"output":"d","net_in":[1,2],"net_out":3}"#);
+        assert_eq!(serde_json::from_str::<Gates>(&text).unwrap(), gate);
+
+        let wire = Wire { net: 4, fanout: vec![0, 2], wiretype: WireType::PrimaryInput, level: FiveLogic::ZERO };
+        let text = serde_json::to_string(&wire).unwrap();
+        assert_eq!(text, r#"{"net":4,"fanout":[0,2],"wiretype":"PrimaryInput","level":"0"}"#);
+        assert_eq!(serde_json::from_str::<Wire>(&text).unwrap(), wire);
+    }
+}
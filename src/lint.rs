@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::gates::{Circuit, Gates};
+
+#[derive(Debug, PartialEq)]
+pub enum Issue {
+    MultipleDrivers { net: String, gates: Vec<usize> },
+    Undriven { net: String },
+    Unused { net: String, gate: usize },
+    UndrivenOutput { net: String },
+    UnusedInput { net: String },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::MultipleDrivers { net, gates } => {
+                write!(f, "net {} is driven by {} gates:", net, gates.len())?;
+                for g in gates {
+                    write!(f, " {}", g)?;
+                }
+                Ok(())
+            },
+            Issue::Undriven { net } => write!(f, "net {} is used as an input but never driven and not declared INPUT", net),
+            Issue::Unused { net, gate } => write!(f, "net {} driven by gate {} goes nowhere", net, gate),
+            Issue::UndrivenOutput { net } => write!(f, "OUTPUT net {} is never driven", net),
+            Issue::UnusedInput { net } => write!(f, "INPUT net {} has no fanout", net),
+        }
+    }
+}
+
+// Flop Q nets count as driven and flop D and reset nets as used, the same as primary
+// inputs and outputs. Nets driven only by tri-state buffers are buses and may
+// have any number of drivers. Issues refer to nets by name.
+pub fn lint(circuit: &Circuit) -> Vec<Issue> {
+    let Circuit { gates, wires, .. } = circuit;
+    let mut issues = vec![];
+    let mut drivers: HashMap<u32,Vec<usize>> = HashMap::new();
+
+    for (i, g) in gates.gatestack.iter().enumerate() {
+        drivers.entry(g.net_output()).or_default().push(i);
+    }
+
+    let mut inputs = circuit.inputs.clone();
+    inputs.extend(circuit.flops.iter().map(|f| f.q));
+    let mut outputs = circuit.outputs.clone();
+    outputs.extend(circuit.flops.iter().flat_map(|f| std::iter::once(f.d).chain(f.reset)));
+    let (inputs, outputs) = (&inputs[..], &outputs[..]);
+
+    let mut nets: Vec<u32> = wires.keys().copied().collect();
+    nets.sort();
+
+    for net in &nets {
+        let wire = &wires[net];
+        let driven = drivers.get(net);
+
+        match driven {
+            // a bus driven only by tri-state buffers is meant to be shared
+            Some(d) if d.len() > 1 && !d.iter().all(|g| matches!(gates.gatestack[*g], Gates::TRIBUF(_))) => {
+                issues.push(Issue::MultipleDrivers { net: circuit.netname(*net), gates: d.clone() });
+            },
+            Some(d) => {
+                if wire.fanout.is_empty() && !outputs.contains(net) {
+                    issues.push(Issue::Unused { net: circuit.netname(*net), gate: d[0] });
+                }
+            },
+            None => {
+                if !wire.fanout.is_empty() && !inputs.contains(net) {
+                    issues.push(Issue::Undriven { net: circuit.netname(*net) });
+                }
+            },
+        }
+    }
+
+    for net in outputs {
+        if !drivers.contains_key(net) && !inputs.contains(net) {
+            issues.push(Issue::UndrivenOutput { net: circuit.netname(*net) });
+        }
+    }
+
+    for net in inputs {
+        if wires.get(net).map(|w| w.fanout.is_empty()).unwrap_or(true) && !outputs.contains(net) {
+            issues.push(Issue::UnusedInput { net: circuit.netname(*net) });
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::parselines;
+
+    fn check(text: &str) -> Vec<Issue> {
+        lint(&parselines("test", text.lines().map(String::from)).unwrap())
+    }
+
+    #[test]
+    fn clean() {
+        assert!(check("INV 1 3\nINV 2 4\nAND 1 4 5\nAND 2 3 6\nOR 5 6 7\nINPUT 1 2 -1\nOUTPUT 7 -1").is_empty());
+    }
+
+    #[test]
+    fn every_issue() {
+        let issues = check("AND 1 2 3\nOR 1 4 3\nINV 1 5\nINPUT 1 2 6 -1\nOUTPUT 3 7 -1");
+
+        assert_eq!(issues, vec![
+            Issue::MultipleDrivers { net: "3".to_string(), gates: vec![0, 1] },
+            Issue::Undriven { net: "4".to_string() },
+            Issue::Unused { net: "5".to_string(), gate: 2 },
+            Issue::UndrivenOutput { net: "7".to_string() },
+            Issue::UnusedInput { net: "6".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn tristate_bus() {
+        assert!(check("TRIBUF a ea bus\nTRIBUF b eb bus\nINPUT a b ea eb -1\nOUTPUT bus -1").is_empty());
+
+        let issues = check("TRIBUF a ea bus\nBUF b bus\nINPUT a b ea -1\nOUTPUT bus -1");
+        assert_eq!(issues, vec![Issue::MultipleDrivers { net: "bus".to_string(), gates: vec![0, 1] }]);
+    }
+
+    #[test]
+    fn named_nets() {
+        let issues = check("AND a b sum\nINV a sum\nINPUT a b -1\nOUTPUT sum -1");
+
+        assert_eq!(issues[0].to_string(), "net sum is driven by 2 gates: 0 1");
+    }
+}
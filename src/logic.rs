@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+
+use crate::cells;
+use crate::gates::{self, Circuit, FiveLogic, GateStack, Gates, Nets, Wire};
+use crate::stdlogic::StdLogic;
+
+// The operations a value system has to provide for the gates to be simulated
+// over it.
+pub trait Logic: Copy + PartialEq {
+    const ZERO: Self;
+    const ONE: Self;
+    // what contention and inputs that cannot be decided give
+    const UNKNOWN: Self;
+    // the value of a net before anything drives it
+    const UNINITIALIZED: Self;
+    // the output of a disabled tri-state driver
+    const FLOAT: Self;
+
+    fn not(self) -> Self;
+    fn and(self, other: Self) -> Self;
+    fn or(self, other: Self) -> Self;
+    fn xor(self, other: Self) -> Self;
+    // the value of a net with both drivers on it
+    fn resolve(self, other: Self) -> Self;
+    // what a buffer driving this value puts out
+    fn strong(self) -> Self;
+
+    // A truth table read for these pin values, the first pin most
+    // significant.
+    fn lookup(table: &[bool], inputs: &[Self]) -> Self {
+        shannon(table, inputs)
+    }
+}
+
+// Three-valued logic for X simulation, 0 and 1 plus an unknown.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ThreeLogic {
+    ZERO,
+    ONE,
+    X,
+}
+
+impl Logic for ThreeLogic {
+    const ZERO: Self = ThreeLogic::ZERO;
+    const ONE: Self = ThreeLogic::ONE;
+    const UNKNOWN: Self = ThreeLogic::X;
+    const UNINITIALIZED: Self = ThreeLogic::X;
+    // there is no Z, so a bus is known only where all its drivers agree
+    const FLOAT: Self = ThreeLogic::X;
+
+    fn not(self) -> Self {
+        match self {
+            ThreeLogic::ZERO => ThreeLogic::ONE,
+            ThreeLogic::ONE => ThreeLogic::ZERO,
+            ThreeLogic::X => ThreeLogic::X,
+        }
+    }
+
+    fn and(self, other: Self) -> Self {
+        match (self, other) {
+            (ThreeLogic::ZERO, _) | (_, ThreeLogic::ZERO) => ThreeLogic::ZERO,
+            (ThreeLogic::ONE, ThreeLogic::ONE) => ThreeLogic::ONE,
+            _ => ThreeLogic::X,
+        }
+    }
+
+    fn or(self, other: Self) -> Self {
+        match (self, other) {
+            (ThreeLogic::ONE, _) | (_, ThreeLogic::ONE) => ThreeLogic::ONE,
+            (ThreeLogic::ZERO, ThreeLogic::ZERO) => ThreeLogic::ZERO,
+            _ => ThreeLogic::X,
+        }
+    }
+
+    fn xor(self, other: Self) -> Self {
+        match (self, other) {
+            (ThreeLogic::X, _) | (_, ThreeLogic::X) => ThreeLogic::X,
+            _ if self == other => ThreeLogic::ZERO,
+            _ => ThreeLogic::ONE,
+        }
+    }
+
+    fn resolve(self, other: Self) -> Self {
+        if self == other { self } else { ThreeLogic::X }
+    }
+
+    fn strong(self) -> Self {
+        self
+    }
+}
+
+// Two-valued logic for fast simulation once every input is known. Nothing is
+// unknown here: undriven nets and floating buses read 0, and a bus is the OR
+// of its drivers.
+impl Logic for bool {
+    const ZERO: Self = false;
+    const ONE: Self = true;
+    const UNKNOWN: Self = false;
+    const UNINITIALIZED: Self = false;
+    const FLOAT: Self = false;
+
+    fn not(self) -> Self {
+        !self
+    }
+
+    fn and(self, other: Self) -> Self {
+        self & other
+    }
+
+    fn or(self, other: Self) -> Self {
+        self | other
+    }
+
+    fn xor(self, other: Self) -> Self {
+        self ^ other
+    }
+
+    fn resolve(self, other: Self) -> Self {
+        self | other
+    }
+
+    fn strong(self) -> Self {
+        self
+    }
+
+    fn lookup(table: &[bool], inputs: &[Self]) -> Self {
+        let n = table.len().trailing_zeros() as usize;
+        let row = (0..n).fold(0, |row, k| row << 1 | inputs.get(k).copied().unwrap_or(false) as usize);
+        table[row]
+    }
+}
+
+impl Logic for FiveLogic {
+    const ZERO: Self = FiveLogic::ZERO;
+    const ONE: Self = FiveLogic::ONE;
+    const UNKNOWN: Self = FiveLogic::X;
+    const UNINITIALIZED: Self = FiveLogic::X;
+    const FLOAT: Self = FiveLogic::Z;
+
+    fn not(self) -> Self {
+        gates::invert(&self)
+    }
+
+    fn and(self, other: Self) -> Self {
+        gates::and(self, other)
+    }
+
+    fn or(self, other: Self) -> Self {
+        gates::or(self, other)
+    }
+
+    fn xor(self, other: Self) -> Self {
+        gates::xor(self, other)
+    }
+
+    fn resolve(self, other: Self) -> Self {
+        gates::resolve(self, other)
+    }
+
+    fn strong(self) -> Self {
+        if self == FiveLogic::Z { FiveLogic::X } else { self }
+    }
+
+    // machine by machine, which is exact where splitting on pins is not
+    fn lookup(table: &[bool], inputs: &[Self]) -> Self {
+        cells::lookup(table, inputs)
+    }
+}
+
+impl Logic for StdLogic {
+    const ZERO: Self = StdLogic::ZERO;
+    const ONE: Self = StdLogic::ONE;
+    const UNKNOWN: Self = StdLogic::X;
+    const UNINITIALIZED: Self = StdLogic::U;
+    const FLOAT: Self = StdLogic::Z;
+
+    fn not(self) -> Self {
+        StdLogic::not(self)
+    }
+
+    fn and(self, other: Self) -> Self {
+        StdLogic::and(self, other)
+    }
+
+    fn or(self, other: Self) -> Self {
+        StdLogic::or(self, other)
+    }
+
+    fn xor(self, other: Self) -> Self {
+        StdLogic::xor(self, other)
+    }
+
+    fn resolve(self, other: Self) -> Self {
+        StdLogic::resolve(self, other)
+    }
+
+    fn strong(self) -> Self {
+        StdLogic::strong(self)
+    }
+}
+
+// Reads a truth table by splitting it on its first pin: the halves for that
+// pin at 0 and at 1 are read on the remaining pins and multiplexed. Where the
+// halves agree the pin cannot matter, so an unknown value on it passes the
+// agreed output through.
+pub fn shannon<L: Logic>(table: &[bool], inputs: &[L]) -> L {
+    if table.len() <= 1 {
+        return if table.first() == Some(&true) { L::ONE } else { L::ZERO };
+    }
+
+    let half = table.len() / 2;
+    let rest = inputs.get(1..).unwrap_or(&[]);
+    let low = shannon(&table[..half], rest);
+    let high = shannon(&table[half..], rest);
+    if low == high {
+        return low;
+    }
+
+    let select = inputs.first().copied().unwrap_or(L::UNKNOWN);
+    select.and(high).or(select.not().and(low))
+}
+
+// Simulates the circuit over any value system from its power-up state and
+// returns the value of every net.
+pub fn simulatewith<L: Logic>(circuit: &mut Circuit, inputs: &[L]) -> HashMap<u32,L> {
+    let state = powerup(circuit);
+    settle(circuit, inputs, &state)
+}
+
+// The flop values at power-up: their initial value where it is 0 or 1,
+// otherwise uninitialized.
+pub fn powerup<L: Logic>(circuit: &Circuit) -> Vec<L> {
+    circuit.flops.iter().map(|flop| match flop.init {
+        FiveLogic::ZERO => L::ZERO,
+        FiveLogic::ONE => L::ONE,
+        _ => L::UNINITIALIZED,
+    }).collect()
+}
+
+// One clock cycle. The logic settles with the inputs and the flops holding
+// `state`, then every flop loads its D net, or 0 where its reset is 1. An
+// unknown reset leaves the flop unknown unless D is 0 as well. Returns the
+// settled net values and the state after the clock edge.
+pub fn clock<L: Logic>(circuit: &mut Circuit, state: &[L], inputs: &[L]) -> (HashMap<u32,L>, Vec<L>) {
+    let values = settle(circuit, inputs, state);
+    let value = |net: u32| values.get(&net).copied().unwrap_or(L::UNINITIALIZED);
+
+    let next = circuit.flops.iter().map(|flop| match flop.reset {
+        Some(reset) => value(flop.d).and(value(reset).not()),
+        None => value(flop.d),
+    }).collect();
+
+    (values, next)
+}
+
+// Net values and gate outputs for simulating in a value system other than
+// the one the wires carry. Nets start uninitialized.
+struct Values<'a, L> {
+    wires: &'a HashMap<u32,Wire>,
+    levels: HashMap<u32,L>,
+    outputs: Vec<L>,
+}
+
+impl<L: Logic> Nets for Values<'_, L> {
+    type Level = L;
+
+    fn wires(&self) -> &HashMap<u32,Wire> {
+        self.wires
+    }
+
+    fn level(&self, net: u32) -> L {
+        self.levels.get(&net).copied().unwrap_or(L::UNINITIALIZED)
+    }
+
+    fn setlevel(&mut self, net: u32, level: L) {
+        self.levels.insert(net, level);
+    }
+
+    fn output(&self, _: &GateStack, gate: usize) -> L {
+        self.outputs.get(gate).copied().unwrap_or(L::UNINITIALIZED)
+    }
+
+    fn evaluated(&mut self, _: &mut Gates, i: usize, _: &[L], output: L) {
+        if self.outputs.len() <= i {
+            self.outputs.resize(i + 1, L::UNINITIALIZED);
+        }
+        self.outputs[i] = output;
+    }
+}
+
+// Evaluates every gate once in level order, with each bus resolved across
+// all of its drivers. Nets start uninitialized apart from the inputs and the
+// flop outputs.
+fn settle<L: Logic>(circuit: &mut Circuit, inputs: &[L], state: &[L]) -> HashMap<u32,L> {
+    let mut values = Values { wires: &circuit.wires, levels: HashMap::new(), outputs: vec![] };
+    for (net, value) in circuit.inputs.iter().zip(inputs) {
+        values.setlevel(*net, *value);
+    }
+    for (flop, value) in circuit.flops.iter().zip(state) {
+        values.setlevel(flop.q, *value);
+    }
+
+    gates::simulate(&mut circuit.gates, &mut values);
+    values.levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::{parselines, simulate};
+
+    fn circuit(text: &str) -> Circuit {
+        parselines("test", text.lines().map(String::from)).unwrap()
+    }
+
+    fn std(text: &str) -> Vec<StdLogic> {
+        text.chars().filter_map(StdLogic::parse).collect()
+    }
+
+    #[test]
+    fn nine_valued() {
+        let mut circuit = circuit("AND a b p\nOR p c q\nINV w r\nINPUT a b c w -1\nOUTPUT p q r -1");
+        let outputs = |circuit: &mut Circuit, values: &[StdLogic]| {
+            let nets = simulatewith(circuit, values);
+            circuit.outputs.iter().map(|o| nets[o]).collect::<Vec<StdLogic>>()
+        };
+
+        assert_eq!(outputs(&mut circuit, &std("0U1H")), std("010"));
+        assert_eq!(outputs(&mut circuit, &std("1UL0")), std("UU1"));
+        assert_eq!(outputs(&mut circuit, &std("HXZW")), std("XXX"));
+
+        // inputs that are never given stay uninitialized
+        assert_eq!(outputs(&mut circuit, &std("11")), std("11U"));
+    }
+
+    #[test]
+    fn buses_and_cells() {
+        let mut circuit = circuit("TRIBUF a ea bus\nTRIBUF b eb bus\nMUX2 a b s y\nINPUT a b ea eb s -1\nOUTPUT bus y -1");
+        let mut run = |values: &str| {
+            let nets = simulatewith(&mut circuit, &std(values));
+            let (bus, y) = (circuit.outputs[0], circuit.outputs[1]);
+            format!("{}{}", nets[&bus], nets[&y])
+        };
+
+        assert_eq!(run("1000U"), "ZU");
+        assert_eq!(run("10100"), "11");
+        assert_eq!(run("1011X"), "XX");
+        assert_eq!(run("L1U01"), "U1");
+        // as in VHDL, the MUX2 expression does not see past an unknown select
+        assert_eq!(run("11H0X"), "1X");
+    }
+
+    #[test]
+    fn five_valued_agrees() {
+        let mut circuit = circuit("NAND a b p\nXOR p c q\nTRIBUF q e y\nOAI21 a b c z\nINPUT a b c e -1\nOUTPUT y z -1");
+        let values = [FiveLogic::ZERO, FiveLogic::ONE, FiveLogic::D, FiveLogic::Dnot, FiveLogic::X];
+
+        for v in 0..625 {
+            let vector: Vec<FiveLogic> = (0..4).map(|k| values[(v / 5usize.pow(k)) % 5]).collect();
+            let nets = simulatewith(&mut circuit, &vector);
+
+            for (net, value) in circuit.inputs.clone().iter().zip(&vector) {
+                circuit.wires.get_mut(net).unwrap().level = *value;
+            }
+            simulate(&mut circuit.gates, &mut circuit.wires);
+
+            for o in &circuit.outputs {
+                assert_eq!(nets[o], circuit.wires[o].level, "vector {:?}", vector);
+            }
+        }
+    }
+
+    #[test]
+    fn algebras_agree() {
+        let text = "DEFGATE MAJ3 3 00010111\nNAND a b p\nXNOR p c d q\nMUX2 a q c r\nMAJ3 p r d s\nINV s t\nINPUT a b c d -1\nOUTPUT q r t -1";
+        let mut circuit = circuit(text);
+
+        for v in 0..16 {
+            let bits: Vec<bool> = (0..4).map(|k| (v >> k) & 1 == 1).collect();
+            let two = simulatewith(&mut circuit, &bits);
+
+            let three: Vec<ThreeLogic> = bits.iter().map(|b| if *b { ThreeLogic::ONE } else { ThreeLogic::ZERO }).collect();
+            let five: Vec<FiveLogic> = bits.iter().map(|b| if *b { FiveLogic::ONE } else { FiveLogic::ZERO }).collect();
+            let nine: Vec<StdLogic> = bits.iter().map(|b| if *b { StdLogic::ONE } else { StdLogic::ZERO }).collect();
+            let (three, five, nine) = (simulatewith(&mut circuit, &three), simulatewith(&mut circuit, &five), simulatewith(&mut circuit, &nine));
+
+            for o in &circuit.outputs {
+                let one = two[o];
+                assert_eq!(three[o] == ThreeLogic::ONE, one);
+                assert_eq!(five[o] == FiveLogic::ONE, one);
+                assert_eq!(nine[o] == StdLogic::ONE, one);
+                assert!(three[o] != ThreeLogic::X && five[o] != FiveLogic::X && nine[o] != StdLogic::X);
+            }
+        }
+    }
+
+    #[test]
+    fn three_valued() {
+        let mut circuit = circuit("DEFGATE MUXT 3 00011011\nMUX2 a b s y\nMUXT a b s z\nTRIBUF a e w\nTRIBUF b f w\nINPUT a b s e f -1\nOUTPUT y z w -1");
+        let (zero, one, x) = (ThreeLogic::ZERO, ThreeLogic::ONE, ThreeLogic::X);
+        let mut run = |values: &[ThreeLogic]| {
+            let nets = simulatewith(&mut circuit, values);
+            circuit.outputs.iter().map(|o| nets[o]).collect::<Vec<ThreeLogic>>()
+        };
+
+        // the expression cannot see past an X select, the table can
+        assert_eq!(run(&[one, one, x, one, one]), vec![x, one, one]);
+        assert_eq!(run(&[zero, one, one, one, zero]), vec![one, one, x]);
+        assert_eq!(run(&[one, zero, zero, one, one]), vec![one, one, x]);
+    }
+
+    #[test]
+    fn clocked() {
+        let mut circuit = circuit("DFF a q1 r\nDFF q1 q2\nAND q1 q2 y\nINPUT a r -1\nOUTPUT y q2 -1");
+        let vectors = ["11", "10", "10", "0X", "1X", "00"];
+
+        let mut state: Vec<FiveLogic> = powerup(&circuit);
+        let mut seen = vec![];
+        for vector in vectors {
+            let inputs: Vec<FiveLogic> = vector.chars().map(|c| gates::parselevel(&c.to_string())).collect();
+            let (nets, next) = clock(&mut circuit, &state, &inputs);
+            state = next;
+            seen.push(circuit.outputs.iter().map(|o| gates::levelname(nets[o])).collect::<String>());
+        }
+
+        // the reset clears q1 on the first edge, and an unknown reset only
+        // spares it when D is 0
+        assert_eq!(seen, vec!["XX", "0X", "00", "11", "01", "00"]);
+        assert_eq!(state, vec![FiveLogic::ZERO, FiveLogic::X]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::gates::{netname, GateStack, Wire};
+
+pub struct Loop {
+    pub gates: Vec<usize>,
+    pub nets: Vec<u32>,
+}
+
+fn successors(gates: &GateStack, wires: &HashMap<u32,Wire>, gate: usize) -> Vec<usize> {
+    match wires.get(&gates.gatestack[gate].net_output()) {
+        Some(wire) => wire.fanout.iter().map(|f| *f as usize).collect(),
+        None => vec![],
+    }
+}
+
+// Finds the strongly connected components of the gate graph (an edge runs from
+// a gate to every gate on the fanout of its output net) using an iterative
+// version of Tarjan's algorithm. Every component with more than one gate, or a
+// single gate feeding itself, is a combinational feedback loop.
+pub fn findloops(gates: &GateStack, wires: &HashMap<u32,Wire>) -> Vec<Loop> {
+    let count = gates.gatestack.len();
+    let mut index: Vec<Option<usize>> = vec![None; count];
+    let mut lowlink: Vec<usize> = vec![0; count];
+    let mut onstack: Vec<bool> = vec![false; count];
+    let mut stack: Vec<usize> = vec![];
+    let mut next = 0;
+    let mut components: Vec<Vec<usize>> = vec![];
+
+    for root in 0..count {
+        if index[root].is_some() {
+            continue;
+        }
+
+        // each frame is a gate and the position of the next successor to visit
+        let mut frames: Vec<(usize, usize)> = vec![(root, 0)];
+        index[root] = Some(next);
+        lowlink[root] = next;
+        next += 1;
+        stack.push(root);
+        onstack[root] = true;
+
+        while let Some((gate, pos)) = frames.pop() {
+            let succ = successors(gates, wires, gate);
+
+            if pos < succ.len() {
+                frames.push((gate, pos + 1));
+                let s = succ[pos];
+
+                match index[s] {
+                    None => {
+                        index[s] = Some(next);
+                        lowlink[s] = next;
+                        next += 1;
+                        stack.push(s);
+                        onstack[s] = true;
+                        frames.push((s, 0));
+                    },
+                    Some(i) => {
+                        if onstack[s] {
+                            lowlink[gate] = lowlink[gate].min(i);
+                        }
+                    },
+                }
+                continue;
+            }
+
+            if Some(lowlink[gate]) == index[gate] {
+                let mut component = vec![];
+                while let Some(g) = stack.pop() {
+                    onstack[g] = false;
+                    component.push(g);
+                    if g == gate {
+                        break
+                    }
+                }
+                components.push(component);
+            }
+
+            if let Some((parent, _)) = frames.last() {
+                lowlink[*parent] = lowlink[*parent].min(lowlink[gate]);
+            }
+        }
+    }
+
+    let mut loops = vec![];
+
+    for mut component in components {
+        if component.len() == 1 && !successors(gates, wires, component[0]).contains(&component[0]) {
+            continue;
+        }
+
+        component.sort();
+
+        let mut nets: Vec<u32> = component.iter()
+            .filter(|g| successors(gates, wires, **g).iter().any(|s| component.contains(s)))
+            .map(|g| gates.gatestack[*g].net_output())
+            .collect();
+        nets.sort();
+        nets.dedup();
+
+        loops.push(Loop { gates: component, nets });
+    }
+
+    loops.sort_by_key(|l| l.gates[0]);
+
+    loops
+}
+
+pub fn reportloops(gates: &GateStack, names: &HashMap<u32,String>, loops: &[Loop]) {
+    for l in loops {
+        println!("Error, combinational loop through {} gates:", l.gates.len());
+        for g in &l.gates {
+            let gate = &gates.gatestack[*g];
+            print!("  gate {} {}", g, gate.name());
+            for net in gate.net_inputs() {
+                print!(" {}", netname(names, net));
+            }
+            println!(" {}", netname(names, gate.net_output()));
+        }
+        print!("  on nets");
+        for net in &l.nets {
+            print!(" {}", netname(names, *net));
+        }
+        println!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::{parselines, Circuit};
+
+    fn circuit(text: &str) -> Circuit {
+        parselines("test", text.lines().map(String::from)).unwrap()
+    }
+
+    #[test]
+    fn no_loops() {
+        let Circuit { gates, wires, .. } = circuit("INV 1 3\nINV 2 4\nAND 1 4 5\nAND 2 3 6\nOR 5 6 7\nINPUT 1 2 -1\nOUTPUT 7 -1");
+
+        assert!(findloops(&gates, &wires).is_empty());
+    }
+
+    #[test]
+    fn latch_loop() {
+        let Circuit { gates, wires, .. } = circuit("NAND 1 4 3\nNAND 2 3 4\nBUF 3 5\nINPUT 1 2 -1\nOUTPUT 5 -1");
+
+        let loops = findloops(&gates, &wires);
+
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].gates, vec![0, 1]);
+        assert_eq!(loops[0].nets, vec![3, 4]);
+    }
+
+    #[test]
+    fn self_loop() {
+        let Circuit { gates, wires, .. } = circuit("AND 1 2 2\nINV 1 3\nINV 3 4\nOR 4 5 5\nINPUT 1 -1\nOUTPUT 5 -1");
+
+        let loops = findloops(&gates, &wires);
+
+        assert_eq!(loops.len(), 2);
+        assert_eq!(loops[0].gates, vec![0]);
+        assert_eq!(loops[0].nets, vec![2]);
+        assert_eq!(loops[1].gates, vec![3]);
+        assert_eq!(loops[1].nets, vec![5]);
+    }
+}
@@ -1,7 +1,25 @@
+#![allow(clippy::upper_case_acronyms)]
+
 use crate::gates::Gates::*;
-use clap::Parser;
+use clap::{Parser,Subcommand};
 
+mod aiger;
+mod atpg;
+mod bench;
+mod blif;
+mod cells;
+mod constants;
+mod dot;
+mod fault;
 mod gates;
+mod json;
+mod lint;
+mod logic;
+mod loops;
+mod scan;
+mod stdlogic;
+mod svg;
+mod verilog;
 
 //use gates::{FiveLogic,Gate};
 #[derive(Parser,Debug)]
@@ -9,17 +27,420 @@ mod gates;
 #[command(author = "Taylor Walsh")]
 #[command(version = "0.1.0")]
 #[command(about = "Simulates boolean combinational logic circuits", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args{
-    filename: String,
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    #[arg(required = true)]
+    filename: Option<String>,
 
     verbose: Option<bool>,
     #[clap(short, long, value_parser, use_value_delimiter = true)]
     inputvec: Vec<u8>,
+    #[clap(long)]
+    vectors: Option<String>,
+}
+
+#[derive(Subcommand,Debug)]
+enum Commands {
+    /// Checks a netlist for multiply driven, floating and unused nets
+    Lint {
+        filename: String,
+    },
+    /// Writes a netlist out in the format given by the output file extension
+    Convert {
+        filename: String,
+        output: String,
+    },
+    /// Writes the circuit as a Graphviz digraph
+    Dot {
+        filename: String,
+        /// File to write, standard output if not given
+        #[clap(short, long)]
+        output: Option<String>,
+        /// Input values (0, 1, X, D or d) to simulate and color the nets with
+        #[clap(short, long, use_value_delimiter = true)]
+        inputvec: Vec<String>,
+    },
+    /// Draws the circuit as an SVG schematic
+    Svg {
+        filename: String,
+        /// File to write, standard output if not given
+        #[clap(short, long)]
+        output: Option<String>,
+        /// Input values (0, 1, X, D or d) to simulate and annotate the nets with
+        #[clap(short, long, use_value_delimiter = true)]
+        inputvec: Vec<String>,
+    },
+    /// Folds tie-off constants into the logic and lists the faults they make untestable
+    Constants {
+        filename: String,
+        /// File to write the simplified netlist to, in the format given by its extension
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+    /// Lists the pin stuck-at faults and, given vectors, which ones they detect
+    Faults {
+        filename: String,
+        /// File of input vectors to fault simulate
+        #[clap(long)]
+        vectors: Option<String>,
+    },
+    /// Replaces the flops with scan flops stitched into scan chains
+    Scan {
+        filename: String,
+        /// Number of scan chains
+        #[clap(long, default_value_t = 1)]
+        chains: usize,
+        /// File to write the scan netlist to, in the format given by its extension
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+    /// Generates stuck-at test patterns, loading and unloading the flops through scan chains
+    Atpg {
+        filename: String,
+        /// Number of scan chains
+        #[clap(long, default_value_t = 1)]
+        chains: usize,
+        /// Backtracks allowed per fault before it is aborted
+        #[clap(long, default_value_t = 1000)]
+        backtracks: usize,
+        /// File to write the patterns to, standard output if not given
+        #[clap(short, long)]
+        output: Option<String>,
+        /// File to write the patterns to as one input vector per clock cycle of the scan netlist
+        #[clap(long)]
+        cycles: Option<String>,
+    },
+    /// Clocks the circuit through a sequence of input vectors, one per cycle
+    Cycles {
+        filename: String,
+        /// File of input vectors, one line per clock cycle
+        vectors: String,
+    },
+    /// Simulates the circuit in IEEE 1164 nine-valued logic (U, X, 0, 1, Z, W, L, H, -)
+    StdLogic {
+        filename: String,
+        /// Input values, inputs not given are U
+        #[clap(short, long, use_value_delimiter = true)]
+        inputvec: Vec<String>,
+        /// File of input vectors, one character per input
+        #[clap(long)]
+        vectors: Option<String>,
+    },
+}
+
+type Drawing = fn(&gates::Circuit, bool, &mut Vec<u8>) -> std::io::Result<()>;
+
+fn load(filename: &str) -> gates::Circuit {
+    let parsed = if filename.ends_with(".bench") {
+        bench::parsebench(filename)
+    } else if filename.ends_with(".v") {
+        verilog::parseverilog(filename)
+    } else if filename.ends_with(".blif") {
+        blif::parseblif(filename)
+    } else if filename.ends_with(".aag") || filename.ends_with(".aig") {
+        aiger::parseaiger(filename)
+    } else if filename.ends_with(".json") {
+        json::parsejson(filename)
+    } else {
+        gates::parsegates(filename)
+    };
+
+    match parsed {
+        Ok(circuit) => circuit,
+        Err(e) => {
+            println!("Error, {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn lint(filename: &str) {
+    let issues = lint::lint(&load(filename));
+
+    if issues.is_empty() {
+        println!("No lint issues found");
+        return;
+    }
+
+    for issue in &issues {
+        println!("Error, {}", issue);
+    }
+    std::process::exit(1);
+}
+
+fn convert(filename: &str, output: &str) {
+    save(&load(filename), output);
+}
+
+fn save(circuit: &gates::Circuit, output: &str) {
+    let stem = std::path::Path::new(output).file_stem().and_then(|s| s.to_str()).unwrap_or("top");
+
+    let mut text: Vec<u8> = vec![];
+    let written = if output.ends_with(".bench") {
+        bench::writebench(circuit, &mut text)
+    } else if output.ends_with(".v") {
+        verilog::writeverilog(circuit, stem, &mut text)
+    } else if output.ends_with(".blif") {
+        blif::writeblif(circuit, stem, &mut text)
+    } else if output.ends_with(".aag") || output.ends_with(".aig") {
+        aiger::writeaiger(circuit, output.ends_with(".aig"), &mut text)
+    } else if output.ends_with(".json") {
+        json::writejson(circuit, &mut text)
+    } else {
+        println!("Error, unsupported output format for {}", output);
+        std::process::exit(1);
+    };
+
+    if let Err(e) = written.and_then(|_| std::fs::write(output, text)) {
+        println!("Error, cannot write {}: {}", output, e);
+        std::process::exit(1);
+    }
+}
+
+fn constants(filename: &str, output: Option<String>) {
+    let circuit = load(filename);
+    let found = constants::constants(&circuit);
+
+    for (net, value) in &found {
+        println!("Net {} is constant {}", circuit.netname(*net), if *value == gates::FiveLogic::ONE { 1 } else { 0 });
+    }
+
+    let untestable = fault::untestable(&circuit, &found);
+    for f in &untestable {
+        println!("{} untestable", f.describe(&circuit));
+    }
+    println!("{} of {} faults untestable", untestable.len(), fault::faultlist(&circuit.gates).len());
+
+    let simplified = constants::propagate(&circuit);
+    println!("{} gates simplified to {}", circuit.gates.gatestack.len(), simplified.gates.gatestack.len());
+
+    if let Some(output) = output {
+        save(&simplified, &output);
+    }
+}
+
+fn draw(filename: &str, output: Option<String>, inputvec: Vec<String>, write: Drawing) {
+    let mut circuit = load(filename);
+    let values = !inputvec.is_empty();
+
+    if values {
+        for (m, net) in circuit.inputs.iter().enumerate() {
+            if let Some(wire) = circuit.wires.get_mut(net) {
+                wire.level = inputvec.get(m).map(|v| gates::parselevel(v)).unwrap_or(gates::FiveLogic::X);
+            }
+        }
+        gates::simulate(&mut circuit.gates, &mut circuit.wires);
+    }
+
+    let mut text: Vec<u8> = vec![];
+    let written = write(&circuit, values, &mut text);
+
+    let written = match &output {
+        Some(path) => written.and_then(|_| std::fs::write(path, text)),
+        None => written.and_then(|_| std::io::Write::write_all(&mut std::io::stdout(), &text)),
+    };
+
+    if let Err(e) = written {
+        println!("Error, cannot write {}: {}", output.unwrap_or_default(), e);
+        std::process::exit(1);
+    }
+}
+
+fn faults(filename: &str, vectors: Option<String>) {
+    let mut circuit = load(filename);
+    let faults = fault::faultlist(&circuit.gates);
+
+    let Some(vectorfile) = vectors else {
+        for f in &faults {
+            println!("{}", f.describe(&circuit));
+        }
+        println!("{} faults", faults.len());
+        return;
+    };
+
+    let vectors: Vec<Vec<gates::FiveLogic>> = gates::readvectors(&vectorfile).iter()
+        .map(|v| (0..circuit.inputs.len()).map(|m| gates::tologic(v.get(m))).collect())
+        .collect();
+
+    let mut detected = 0;
+    for f in &faults {
+        match vectors.iter().position(|v| fault::detects(&mut circuit, v, f)) {
+            Some(n) => {
+                detected += 1;
+                println!("{} detected by vector {}", f.describe(&circuit), n + 1);
+            },
+            None => println!("{} undetected", f.describe(&circuit)),
+        }
+    }
+
+    let coverage = if faults.is_empty() { 100.0 } else { 100.0 * detected as f64 / faults.len() as f64 };
+    println!("{} of {} faults detected, {:.1}% coverage", detected, faults.len(), coverage);
+}
+
+fn scan(filename: &str, chains: usize, output: Option<String>) {
+    let circuit = load(filename);
+    let design = scan::insertscan(&circuit, chains);
+
+    for (k, chain) in design.chains.iter().enumerate() {
+        let flops: Vec<String> = chain.iter().map(|f| circuit.netname(circuit.flops[*f].q)).collect();
+        println!("Chain {}, {} flops: {}", k, chain.len(), flops.join(" "));
+    }
+    println!("{} flops in {} chains", circuit.flops.len(), design.chains.len());
+
+    if let Some(output) = output {
+        save(&design.circuit, &output);
+    }
+}
+
+fn atpg(filename: &str, chains: usize, backtracks: usize, output: Option<String>, cycles: Option<String>) {
+    let circuit = load(filename);
+    let mut comb = scan::combinational(&circuit);
+
+    let feedback = loops::findloops(&comb.gates, &comb.wires);
+    if !feedback.is_empty() {
+        loops::reportloops(&comb.gates, &comb.names, &feedback);
+        std::process::exit(1);
+    }
+
+    let (patterns, results) = atpg::generate(&mut comb, circuit.gates.gatestack.len(), backtracks);
+    let design = scan::insertscan(&circuit, chains);
+
+    let mut counts = [0; 3];
+    for (f, status) in &results {
+        match status {
+            atpg::Status::Detected(_) => counts[0] += 1,
+            atpg::Status::Untestable => {
+                counts[1] += 1;
+                println!("{} untestable", f.describe(&comb));
+            },
+            atpg::Status::Aborted => {
+                counts[2] += 1;
+                println!("{} aborted", f.describe(&comb));
+            },
+        }
+    }
+    let coverage = if results.is_empty() { 100.0 } else { 100.0 * counts[0] as f64 / results.len() as f64 };
+    println!("{} patterns, {} of {} faults detected, {} untestable, {} aborted, {:.1}% coverage",
+        patterns.len(), counts[0], results.len(), counts[1], counts[2], coverage);
+
+    let mut text: Vec<u8> = vec![];
+    let written = scan::writepatterns(&circuit, &design, &patterns, &mut text);
+    let written = match &output {
+        Some(path) => written.and_then(|_| std::fs::write(path, text)),
+        None => written.and_then(|_| std::io::Write::write_all(&mut std::io::stdout(), &text)),
+    };
+    if let Err(e) = written {
+        println!("Error, cannot write {}: {}", output.unwrap_or_default(), e);
+        std::process::exit(1);
+    }
+
+    if let Some(path) = cycles {
+        let (vectors, _) = scan::cyclevectors(&circuit, &design, &patterns);
+        let lines: Vec<String> = vectors.iter().map(|v| v.iter().map(|l| gates::levelname(*l)).collect::<String>() + "\n").collect();
+        if let Err(e) = std::fs::write(&path, lines.concat()) {
+            println!("Error, cannot write {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn cycles(filename: &str, vectors: &str) {
+    let mut circuit = load(filename);
+
+    let feedback = loops::findloops(&circuit.gates, &circuit.wires);
+    if !feedback.is_empty() {
+        loops::reportloops(&circuit.gates, &circuit.names, &feedback);
+        std::process::exit(1);
+    }
+
+    println!();
+    println!("Circuit outputs:");
+    let names: Vec<String> = circuit.outputs.iter().map(|o| circuit.netname(*o)).collect();
+    println!("{} ", names.join(" "));
+    println!();
+    println!("Outputs by cycle:");
+
+    let mut state: Vec<gates::FiveLogic> = logic::powerup(&circuit);
+    for (cycle, vector) in gates::readvectors(vectors).iter().enumerate() {
+        let inputs: Vec<gates::FiveLogic> = (0..circuit.inputs.len()).map(|m| gates::tologic(vector.get(m))).collect();
+        let (nets, next) = logic::clock(&mut circuit, &state, &inputs);
+        state = next;
+
+        let values: Vec<&str> = circuit.outputs.iter()
+            .map(|o| gates::levelname(nets.get(o).copied().unwrap_or(gates::FiveLogic::X)))
+            .collect();
+        println!("{}: {} ", cycle + 1, values.join(" "));
+    }
+}
+
+fn stdlogic(filename: &str, inputvec: Vec<String>, vectors: Option<String>) {
+    let mut circuit = load(filename);
+
+    let feedback = loops::findloops(&circuit.gates, &circuit.wires);
+    if !feedback.is_empty() {
+        loops::reportloops(&circuit.gates, &circuit.names, &feedback);
+        std::process::exit(1);
+    }
+
+    let parse = |text: &str| -> Vec<stdlogic::StdLogic> {
+        text.chars().filter(|c| !c.is_whitespace() && *c != ',').map(|c| match stdlogic::StdLogic::parse(c) {
+            Some(value) => value,
+            None => {
+                println!("Error, {} is not a std_logic value", c);
+                std::process::exit(1);
+            }
+        }).collect()
+    };
+
+    let vectors: Vec<Vec<stdlogic::StdLogic>> = match vectors {
+        Some(vectorfile) => match std::fs::read_to_string(&vectorfile) {
+            Ok(text) => text.lines().map(parse).filter(|v| !v.is_empty()).collect(),
+            Err(e) => {
+                println!("Error, cannot read {}: {}", vectorfile, e);
+                std::process::exit(1);
+            }
+        },
+        None => vec![parse(&inputvec.concat())],
+    };
+
+    println!();
+    println!("Circuit outputs:");
+    let names: Vec<String> = circuit.outputs.iter().map(|o| circuit.netname(*o)).collect();
+    println!("{} ", names.join(" "));
+    println!();
+    println!("Output vector{}:", if vectors.len() == 1 { "" } else { "s" });
+
+    for vector in &vectors {
+        let nets = logic::simulatewith(&mut circuit, vector);
+        let values: Vec<String> = circuit.outputs.iter()
+            .map(|o| nets.get(o).copied().unwrap_or(stdlogic::StdLogic::U).to_string())
+            .collect();
+        println!("{} ", values.join(" "));
+    }
 }
 
 fn main() {
 
     let cli = Args::parse();
+
+    if let Some(command) = cli.command {
+        match command {
+            Commands::Lint { filename } => lint(&filename),
+            Commands::Convert { filename, output } => convert(&filename, &output),
+            Commands::Dot { filename, output, inputvec } => draw(&filename, output, inputvec, dot::writedot),
+            Commands::Svg { filename, output, inputvec } => draw(&filename, output, inputvec, svg::writesvg),
+            Commands::Constants { filename, output } => constants(&filename, output),
+            Commands::Faults { filename, vectors } => faults(&filename, vectors),
+            Commands::Scan { filename, chains, output } => scan(&filename, chains, output),
+            Commands::Atpg { filename, chains, backtracks, output, cycles } => atpg(&filename, chains, backtracks, output, cycles),
+            Commands::Cycles { filename, vectors } => cycles(&filename, &vectors),
+            Commands::StdLogic { filename, inputvec, vectors } => stdlogic(&filename, inputvec, vectors),
+        }
+        return;
+    }
     
     //let mut and1 = ANDGate::new();
 
@@ -28,58 +449,77 @@ fn main() {
 
     //and1.eval();
 
-    let filename = cli.filename;
+    let filename = cli.filename.unwrap_or_default();
 
-    let (mut gates, mut wires, inputs, outputs) = gates::parsegates(&filename);
+    let gates::Circuit { mut gates, mut wires, inputs, outputs, names, .. } = load(&filename);
+    let name = |net: u32| gates::netname(&names, net);
 
-    let mode = {
-        match cli.verbose {
-            Some(verb) => verb,
-            None => false,
-        }
-    };
+    let feedback = loops::findloops(&gates, &wires);
+    if !feedback.is_empty() {
+        loops::reportloops(&gates, &names, &feedback);
+        std::process::exit(1);
+    }
+
+    gates::levelize(&mut gates, &wires);
+
+    let mode = cli.verbose.unwrap_or_default();
 
 
     if mode {
-        println!("");
+        println!();
+
+        let pins = |nets: &[u32]| -> String {
+            let pins: Vec<String> = nets.iter().enumerate().map(|(k, n)| format!("in{}:{}", k + 1, name(*n))).collect();
+            pins.join(" ")
+        };
 
         for g in &gates.gatestack[..] {
             match g {
-                AND(gate) => println!("AND gate, in1:{} in2:{} out:{}",gate.net_in_a,gate.net_in_b,gate.net_out), 
-                NAND(gate) => println!("NAND gate, in1:{} in2:{} out:{}",gate.net_in_a,gate.net_in_b,gate.net_out),
-                OR(gate) => println!("OR gate, in1:{} in2:{} out:{}",gate.net_in_a,gate.net_in_b,gate.net_out),
-                NOR(gate) => println!("NOR gate, in1:{} in2:{} out:{}",gate.net_in_a,gate.net_in_b,gate.net_out),
-                INV(gate) => println!("INV gate, in1:{} out:{}",gate.net_in_a,gate.net_out),
-                BUF(gate) => println!("BUF gate, in1:{} out:{}",gate.net_in_a,gate.net_out),
+                AND(gate) => println!("AND gate, {} out:{}",pins(&gate.net_in),name(gate.net_out)),
+                NAND(gate) => println!("NAND gate, {} out:{}",pins(&gate.net_in),name(gate.net_out)),
+                OR(gate) => println!("OR gate, {} out:{}",pins(&gate.net_in),name(gate.net_out)),
+                NOR(gate) => println!("NOR gate, {} out:{}",pins(&gate.net_in),name(gate.net_out)),
+                XOR(gate) => println!("XOR gate, {} out:{}",pins(&gate.net_in),name(gate.net_out)),
+                XNOR(gate) => println!("XNOR gate, {} out:{}",pins(&gate.net_in),name(gate.net_out)),
+                INV(gate) => println!("INV gate, in1:{} out:{}",name(gate.net_in_a),name(gate.net_out)),
+                BUF(gate) => println!("BUF gate, in1:{} out:{}",name(gate.net_in_a),name(gate.net_out)),
+                TRIBUF(gate) => println!("TRIBUF gate, in1:{} en:{} out:{}",name(gate.net_in[0]),name(gate.net_in[1]),name(gate.net_out)),
+                CELL(gate) => {
+                    let pins: Vec<String> = gate.cell.inputs.iter().zip(&gate.net_in).map(|(p, n)| format!("{}:{}", p, name(*n))).collect();
+                    println!("{} cell, {} {}:{}",gate.cell.name,pins.join(" "),gate.cell.output,name(gate.net_out));
+                },
             }
         }
-        println!("");
-        println!("");
+        println!();
+        println!();
 
-        for w in &wires {
-            println!("Net {} has the following gates as fanouts:",w.0);
-            for f in &w.1.fanout[..] {
+        for w in wires.values() {
+            println!("Net {} has the following gates as fanouts:",name(w.net));
+            for f in &w.fanout[..] {
                 print!("{} ",f);
             }
-            println!("");
-            println!("");
+            println!();
+            println!();
         }
 
         println!("Following nets are primary inputs:");
         for i in &inputs[..] {
-            print!("{} ",i);
+            print!("{} ",name(*i));
         }
-        println!("");
-        println!("");
+        println!();
+        println!();
 
         println!("Following nets are primary outputs:");
         for o in &outputs[..] {
-            print!("{} ",o);
+            print!("{} ",name(*o));
         }
-        println!("");
+        println!();
     }
 
-    gates::logic(&mut gates, &mut wires, inputs, outputs, cli.inputvec);
+    match cli.vectors {
+        Some(vectorfile) => gates::logicstream(&mut gates, &mut wires, inputs, outputs, &names, gates::readvectors(&vectorfile)),
+        None => gates::logic(&mut gates, &mut wires, inputs, outputs, &names, cli.inputvec),
+    }
     
 }
 
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::io::{self,Write};
+
+use crate::atpg::Pattern;
+use crate::gates::{levelname, Circuit, FiveLogic};
+
+// A circuit after full scan insertion. Every flop has a MUX2 in front of its
+// D pin that, with scan enable high, loads the previous flop in its chain
+// instead, so the flops shift as a register from scan in to scan out.
+pub struct ScanDesign {
+    pub circuit: Circuit,
+    // none when there are no flops to scan
+    pub enable: Option<u32>,
+    pub scanins: Vec<u32>,
+    pub scanouts: Vec<u32>,
+    // the flops of each chain, from scan in to scan out
+    pub chains: Vec<Vec<usize>>,
+}
+
+// Splits the flops, in netlist order, into `count` chains whose lengths
+// differ by at most one. There are never more chains than flops.
+pub fn chains(flops: usize, count: usize) -> Vec<Vec<usize>> {
+    let count = count.clamp(1, flops.max(1));
+    let (size, extra) = (flops / count, flops % count);
+
+    let mut chains = vec![];
+    let mut next = 0;
+    for k in 0..count {
+        let length = size + usize::from(k < extra);
+        chains.push((next..next + length).collect());
+        next += length;
+    }
+    chains
+}
+
+fn highest(circuit: &Circuit) -> u32 {
+    circuit.wires.keys().copied().max().unwrap_or(0)
+}
+
+// A net name not yet used in the circuit, the given one if it is free.
+fn freshname(circuit: &Circuit, name: &str) -> String {
+    let taken = |n: &str| circuit.names.values().any(|v| v == n);
+    let mut candidate = name.to_string();
+    let mut k = 1;
+    while taken(&candidate) {
+        candidate = format!("{}_{}", name, k);
+        k += 1;
+    }
+    candidate
+}
+
+// Copies the gates, cells and names of a circuit into a new one, leaving out
+// its inputs, outputs and flops.
+fn logiconly(circuit: &Circuit) -> Circuit {
+    let mut copy = Circuit::new();
+    copy.cells = circuit.cells.clone();
+
+    for gate in &circuit.gates.gatestack {
+        copy.addgate(gate.name(), &gate.net_inputs(), gate.net_output());
+    }
+    copy.names = circuit.names.clone();
+
+    copy
+}
+
+// Replaces every flop with a scan flop and stitches them into `count` chains.
+// The circuit gains a scan enable input, a scan in input per chain and a
+// scan out output per chain, each buffered from the chain's last flop.
+pub fn insertscan(circuit: &Circuit, count: usize) -> ScanDesign {
+    let mut scanned = logiconly(circuit);
+    let mut next = highest(circuit);
+    let mut fresh = |scanned: &mut Circuit, name: Option<String>| {
+        next += 1;
+        if let Some(name) = name {
+            let name = freshname(scanned, &name);
+            scanned.names.insert(next, name);
+        }
+        next
+    };
+
+    let (enable, chains) = match circuit.flops.len() {
+        0 => (None, vec![]),
+        flops => (Some(fresh(&mut scanned, Some("scan_enable".to_string()))), chains(flops, count)),
+    };
+    let mut scanins = vec![];
+    let mut scanouts = vec![];
+
+    if let Some(enable) = enable {
+        for (k, chain) in chains.iter().enumerate() {
+            let scanin = fresh(&mut scanned, Some(format!("scan_in{}", k)));
+            scanins.push(scanin);
+
+            let mut previous = scanin;
+            for f in chain {
+                let flop = &circuit.flops[*f];
+                let d = fresh(&mut scanned, None);
+                scanned.addgate("MUX2", &[flop.d, previous, enable], d);
+                match flop.reset {
+                    Some(reset) => scanned.addresetflop(d, flop.q, flop.init, reset),
+                    None => scanned.addflop(d, flop.q, flop.init),
+                }
+                previous = flop.q;
+            }
+
+            let scanout = fresh(&mut scanned, Some(format!("scan_out{}", k)));
+            scanned.addgate("BUF", &[previous], scanout);
+            scanouts.push(scanout);
+        }
+    }
+
+    for net in &circuit.inputs {
+        scanned.addinput(*net);
+    }
+    for net in enable.iter().chain(&scanins) {
+        scanned.addinput(*net);
+    }
+    for net in circuit.outputs.iter().chain(&scanouts) {
+        scanned.addoutput(*net);
+    }
+
+    ScanDesign { circuit: scanned, enable, scanins, scanouts, chains }
+}
+
+// The combinational circuit pattern generation works on. Flop outputs become
+// pseudo primary inputs after the real ones and the values the flops capture
+// pseudo primary outputs after the real ones, both in flop order. A flop
+// with a reset captures D AND NOT reset, which is built from gates placed
+// after the circuit's own.
+pub fn combinational(circuit: &Circuit) -> Circuit {
+    let mut comb = logiconly(circuit);
+    let mut next = highest(circuit);
+
+    for net in circuit.inputs.iter().chain(circuit.flops.iter().map(|f| &f.q)) {
+        comb.addinput(*net);
+    }
+    for net in &circuit.outputs {
+        comb.addoutput(*net);
+    }
+    for flop in &circuit.flops {
+        let capture = match flop.reset {
+            Some(reset) => {
+                let (inverted, capture) = (next + 1, next + 2);
+                next += 2;
+                comb.addgate("INV", &[reset], inverted);
+                comb.addgate("AND", &[flop.d, inverted], capture);
+                capture
+            },
+            None => flop.d,
+        };
+        comb.addoutput(capture);
+    }
+
+    comb
+}
+
+fn values(values: &[FiveLogic]) -> String {
+    values.iter().map(|v| levelname(*v)).collect()
+}
+
+// The values of one chain in shift order, the flop nearest scan out first.
+// Loads are shifted in and unloads come out in this order.
+fn shiftorder(chain: &[usize], state: &[FiveLogic]) -> Vec<FiveLogic> {
+    chain.iter().rev().map(|f| state[*f]).collect()
+}
+
+// Writes the patterns as scan test sequences. Each pattern loads the chains,
+// forces the primary inputs, measures the primary outputs, pulses the clock
+// to capture and unloads the chains. Chain values are written in shift
+// order, so the first value loaded and the first unloaded belong to the flop
+// nearest scan out.
+pub fn writepatterns<W: Write>(original: &Circuit, design: &ScanDesign, patterns: &[Pattern], out: &mut W) -> io::Result<()> {
+    let (inputs, outputs) = (original.inputs.len(), original.outputs.len());
+    let names = |nets: &[u32]| nets.iter().map(|n| original.netname(*n)).collect::<Vec<String>>().join(" ");
+
+    writeln!(out, "# {} patterns", patterns.len())?;
+    writeln!(out, "# inputs {}", names(&original.inputs))?;
+    writeln!(out, "# outputs {}", names(&original.outputs))?;
+    for (k, chain) in design.chains.iter().enumerate() {
+        let flops: Vec<u32> = chain.iter().map(|f| original.flops[*f].q).collect();
+        writeln!(out, "# chain {}: {} -> {} -> {}", k, design.circuit.netname(design.scanins[k]), names(&flops), design.circuit.netname(design.scanouts[k]))?;
+    }
+
+    for (n, pattern) in patterns.iter().enumerate() {
+        writeln!(out)?;
+        writeln!(out, "pattern {}", n + 1)?;
+        for (k, chain) in design.chains.iter().enumerate() {
+            writeln!(out, "load chain{} {}", k, values(&shiftorder(chain, &pattern.inputs[inputs..])))?;
+        }
+        writeln!(out, "force {}", values(&pattern.inputs[..inputs]))?;
+        writeln!(out, "measure {}", values(&pattern.outputs[..outputs]))?;
+        writeln!(out, "capture")?;
+        for (k, chain) in design.chains.iter().enumerate() {
+            writeln!(out, "unload chain{} {}", k, values(&shiftorder(chain, &pattern.outputs[outputs..])))?;
+        }
+    }
+
+    Ok(())
+}
+
+// The same sequences as input vectors for the scan inserted circuit, one per
+// clock cycle: shift cycles with scan enable high, then a capture cycle with
+// it low. The unload of each pattern overlaps the load of the next, and a
+// final run of shift cycles unloads the last. Returns the vectors and, for
+// each cycle, the output values to expect there, X where nothing is checked.
+pub fn cyclevectors(original: &Circuit, design: &ScanDesign, patterns: &[Pattern]) -> (Vec<Vec<FiveLogic>>, Vec<Vec<FiveLogic>>) {
+    let (inputs, outputs) = (original.inputs.len(), original.outputs.len());
+    let shifts = design.chains.iter().map(|c| c.len()).max().unwrap_or(0);
+    let position: HashMap<u32,usize> = design.circuit.inputs.iter().enumerate().map(|(m, n)| (*n, m)).collect();
+    let observed: HashMap<u32,usize> = design.circuit.outputs.iter().enumerate().map(|(m, n)| (*n, m)).collect();
+
+    let blank = || vec![FiveLogic::ZERO; design.circuit.inputs.len()];
+    let unknown = || vec![FiveLogic::X; design.circuit.outputs.len()];
+    let mut vectors = vec![];
+    let mut expected = vec![];
+
+    for n in 0..=patterns.len() {
+        for t in 0..shifts {
+            let mut vector = blank();
+            let mut expect = unknown();
+            if let Some(enable) = design.enable {
+                vector[position[&enable]] = FiveLogic::ONE;
+            }
+
+            for (k, chain) in design.chains.iter().enumerate() {
+                // shorter chains start shifting late, so every chain is full
+                // on the last shift
+                let late = shifts - chain.len();
+                if let Some(pattern) = patterns.get(n) {
+                    if t >= late {
+                        vector[position[&design.scanins[k]]] = shiftorder(chain, &pattern.inputs[inputs..])[t - late];
+                    }
+                }
+                if let Some(previous) = n.checked_sub(1).map(|p| &patterns[p]) {
+                    if t < chain.len() {
+                        expect[observed[&design.scanouts[k]]] = shiftorder(chain, &previous.outputs[outputs..])[t];
+                    }
+                }
+            }
+
+            vectors.push(vector);
+            expected.push(expect);
+        }
+
+        if let Some(pattern) = patterns.get(n) {
+            let mut vector = blank();
+            let mut expect = unknown();
+            vector[..inputs].copy_from_slice(&pattern.inputs[..inputs]);
+            expect[..outputs].copy_from_slice(&pattern.outputs[..outputs]);
+            vectors.push(vector);
+            expected.push(expect);
+        }
+    }
+
+    (vectors, expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atpg::{generate, Status};
+    use crate::gates::parselines;
+    use crate::logic::{clock, powerup};
+
+    fn circuit(text: &str) -> Circuit {
+        parselines("test", text.lines().map(String::from)).unwrap()
+    }
+
+    const COUNTER: &str = "DFF n0 q0 r\nDFF n1 q1 r\nDFF n2 q2\nINV q0 n0\nXOR q0 q1 n1\nAND q0 q1 c\nXOR c q2 n2\nAND q0 q1 q2 all\nINPUT r -1\nOUTPUT all -1";
+
+    #[test]
+    fn chain_split() {
+        assert_eq!(chains(5, 2), vec![vec![0, 1, 2], vec![3, 4]]);
+        assert_eq!(chains(2, 4), vec![vec![0], vec![1]]);
+        assert_eq!(chains(3, 0), vec![vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn insertion() {
+        let circuit = circuit(COUNTER);
+        let mut design = insertscan(&circuit, 2);
+        let scanned = &design.circuit;
+
+        assert_eq!(design.chains, vec![vec![0, 1], vec![2]]);
+        assert_eq!(scanned.inputs.len(), 4);
+        assert_eq!(scanned.outputs.len(), 3);
+        assert_eq!(scanned.netname(design.enable.unwrap()), "scan_enable");
+        assert_eq!(scanned.netname(design.scanouts[1]), "scan_out1");
+        assert!(crate::lint::lint(scanned).is_empty());
+
+        // with scan enable high each chain shifts along one flop
+        let (zero, one) = (FiveLogic::ZERO, FiveLogic::ONE);
+        let (_, state) = clock(&mut design.circuit, &[one, zero, one], &[zero, one, one, zero]);
+        assert_eq!(state, vec![one, one, zero]);
+
+        let design = insertscan(&crate::gates::parselines("test", "INV a y\nINPUT a -1\nOUTPUT y -1".lines().map(String::from)).unwrap(), 1);
+        assert_eq!((design.enable, design.circuit.inputs.len()), (None, 1));
+    }
+
+    #[test]
+    fn pseudo_inputs() {
+        let circuit = circuit(COUNTER);
+        let comb = combinational(&circuit);
+
+        assert_eq!(comb.inputs.len(), 4);
+        assert_eq!(comb.outputs.len(), 4);
+        assert!(comb.flops.is_empty());
+        // the reset flops capture through an inverter and an AND
+        assert_eq!(comb.gates.gatestack.len(), circuit.gates.gatestack.len() + 4);
+    }
+
+    #[test]
+    fn scan_sequences() {
+        let circuit = circuit(COUNTER);
+        let mut comb = combinational(&circuit);
+        let (patterns, results) = generate(&mut comb, circuit.gates.gatestack.len(), 1000);
+
+        assert!(results.iter().all(|(_, s)| matches!(s, Status::Detected(_))));
+
+        for count in [1, 2] {
+            let mut design = insertscan(&circuit, count);
+            let (vectors, expected) = cyclevectors(&circuit, &design, &patterns);
+
+            let mut state: Vec<FiveLogic> = powerup(&design.circuit);
+            for (vector, expect) in vectors.iter().zip(&expected) {
+                let (nets, next) = clock(&mut design.circuit, &state, vector);
+                state = next;
+                for (o, want) in design.circuit.outputs.iter().zip(expect) {
+                    if *want != FiveLogic::X {
+                        assert_eq!(nets[o], *want);
+                    }
+                }
+            }
+        }
+
+        let design = insertscan(&circuit, 2);
+        let mut text: Vec<u8> = vec![];
+        writepatterns(&circuit, &design, &patterns, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("# chain 0: scan_in0 -> q0 q1 -> scan_out0\n"));
+        assert!(text.contains("\npattern 1\nload chain0 "));
+    }
+}
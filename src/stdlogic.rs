@@ -0,0 +1,180 @@
+use std::fmt;
+
+use serde::{Deserialize,Serialize};
+
+// The nine values of IEEE 1164 std_ulogic, in the standard's order.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum StdLogic {
+    U,
+    X,
+    #[serde(rename = "0")]
+    ZERO,
+    #[serde(rename = "1")]
+    ONE,
+    Z,
+    W,
+    L,
+    H,
+    #[serde(rename = "-")]
+    DontCare,
+}
+
+const ORDER: [StdLogic; 9] = [
+    StdLogic::U, StdLogic::X, StdLogic::ZERO, StdLogic::ONE, StdLogic::Z,
+    StdLogic::W, StdLogic::L, StdLogic::H, StdLogic::DontCare,
+];
+
+// The tables of the std_logic_1164 package body, row by left operand and
+// column by right operand, both in U X 0 1 Z W L H - order.
+const RESOLUTION: [&str; 9] = [
+    "UUUUUUUUU",
+    "UXXXXXXXX",
+    "UX0X0000X",
+    "UXX11111X",
+    "UX01ZWLHX",
+    "UX01WWWWX",
+    "UX01LWLWX",
+    "UX01HWWHX",
+    "UXXXXXXXX",
+];
+
+const AND: [&str; 9] = [
+    "UU0UUU0UU",
+    "UX0XXX0XX",
+    "000000000",
+    "UX01XX01X",
+    "UX0XXX0XX",
+    "UX0XXX0XX",
+    "000000000",
+    "UX01XX01X",
+    "UX0XXX0XX",
+];
+
+const OR: [&str; 9] = [
+    "UUU1UUU1U",
+    "UXX1XXX1X",
+    "UX01XX01X",
+    "111111111",
+    "UXX1XXX1X",
+    "UXX1XXX1X",
+    "UX01XX01X",
+    "111111111",
+    "UXX1XXX1X",
+];
+
+const XOR: [&str; 9] = [
+    "UUUUUUUUU",
+    "UXXXXXXXX",
+    "UX01XX01X",
+    "UX10XX10X",
+    "UXXXXXXXX",
+    "UXXXXXXXX",
+    "UX01XX01X",
+    "UX10XX10X",
+    "UXXXXXXXX",
+];
+
+const NOT: &str = "UX10XX10X";
+
+impl StdLogic {
+    fn index(self) -> usize {
+        ORDER.iter().position(|v| *v == self).unwrap_or(0)
+    }
+
+    pub fn parse(c: char) -> Option<StdLogic> {
+        match c {
+            'U' | 'u' => Some(StdLogic::U),
+            'X' | 'x' => Some(StdLogic::X),
+            '0' => Some(StdLogic::ZERO),
+            '1' => Some(StdLogic::ONE),
+            'Z' | 'z' => Some(StdLogic::Z),
+            'W' | 'w' => Some(StdLogic::W),
+            'L' | 'l' => Some(StdLogic::L),
+            'H' | 'h' => Some(StdLogic::H),
+            '-' => Some(StdLogic::DontCare),
+            _ => None,
+        }
+    }
+
+    fn lookup(table: &[&str; 9], a: StdLogic, b: StdLogic) -> StdLogic {
+        let c = table[a.index()].as_bytes()[b.index()] as char;
+        StdLogic::parse(c).unwrap_or(StdLogic::X)
+    }
+
+    pub fn not(self) -> StdLogic {
+        StdLogic::parse(NOT.as_bytes()[self.index()] as char).unwrap_or(StdLogic::X)
+    }
+
+    pub fn and(self, other: StdLogic) -> StdLogic {
+        StdLogic::lookup(&AND, self, other)
+    }
+
+    pub fn or(self, other: StdLogic) -> StdLogic {
+        StdLogic::lookup(&OR, self, other)
+    }
+
+    pub fn xor(self, other: StdLogic) -> StdLogic {
+        StdLogic::lookup(&XOR, self, other)
+    }
+
+    // The value of a signal with both drivers, the resolved std_logic.
+    pub fn resolve(self, other: StdLogic) -> StdLogic {
+        StdLogic::lookup(&RESOLUTION, self, other)
+    }
+
+    // To_UX01: weak values strengthen, everything else unknown becomes X.
+    pub fn strong(self) -> StdLogic {
+        self.and(StdLogic::ONE)
+    }
+}
+
+impl fmt::Display for StdLogic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", "UX01ZWLH-".as_bytes()[self.index()] as char)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(c: char) -> StdLogic {
+        StdLogic::parse(c).unwrap()
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(v('0').and(v('U')), v('0'));
+        assert_eq!(v('1').and(v('U')), v('U'));
+        assert_eq!(v('H').and(v('1')), v('1'));
+        assert_eq!(v('L').or(v('Z')), v('X'));
+        assert_eq!(v('H').or(v('U')), v('1'));
+        assert_eq!(v('H').xor(v('L')), v('1'));
+        assert_eq!(v('U').xor(v('0')), v('U'));
+        assert_eq!(v('W').not(), v('X'));
+        assert_eq!(v('L').not(), v('1'));
+
+        // the tables are commutative, as in the standard
+        for a in ORDER {
+            for b in ORDER {
+                assert_eq!(a.and(b), b.and(a));
+                assert_eq!(a.or(b), b.or(a));
+                assert_eq!(a.xor(b), b.xor(a));
+                assert_eq!(a.resolve(b), b.resolve(a));
+            }
+        }
+    }
+
+    #[test]
+    fn resolution() {
+        assert_eq!(v('Z').resolve(v('H')), v('H'));
+        assert_eq!(v('L').resolve(v('H')), v('W'));
+        assert_eq!(v('0').resolve(v('L')), v('0'));
+        assert_eq!(v('0').resolve(v('1')), v('X'));
+        assert_eq!(v('U').resolve(v('1')), v('U'));
+        assert_eq!(v('-').resolve(v('Z')), v('X'));
+
+        let text: String = ORDER.iter().map(|s| s.strong().to_string()).collect();
+        assert_eq!(text, "UX01XX01X");
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::io::{self,Write};
+
+use crate::gates::{levelname, levelorder, Circuit, FiveLogic, Gates};
+
+const COLUMN: i32 = 150;
+const ROW: i32 = 70;
+const MARGIN: i32 = 40;
+const SIZE: i32 = 40;
+const BUBBLE: i32 = 4;
+
+fn color(level: FiveLogic) -> &'static str {
+    match level {
+        FiveLogic::D | FiveLogic::Dnot => "red",
+        FiveLogic::ONE => "blue",
+        FiveLogic::ZERO => "darkgreen",
+        FiveLogic::X => "gray",
+        FiveLogic::Z => "orange",
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn colx(column: i32) -> i32 {
+    MARGIN + column * COLUMN
+}
+
+fn rowy(row: i32) -> i32 {
+    MARGIN + row * ROW
+}
+
+// The outline of a gate symbol with its top left corner at x, y, and the x
+// of its output pin.
+fn symbol(gate: &Gates, x: i32, y: i32) -> (String, i32) {
+    let h = SIZE;
+    let half = SIZE / 2;
+
+    let (path, right) = match gate {
+        Gates::AND(_) | Gates::NAND(_) => (
+            format!("M {} {} H {} A {} {} 0 0 1 {} {} H {} Z", x, y, x + half, half, half, x + half, y + h, x),
+            x + SIZE,
+        ),
+        Gates::OR(_) | Gates::NOR(_) => (
+            format!("M {} {} Q {} {} {} {} Q {} {} {} {} Q {} {} {} {} Z",
+                x, y,
+                x + 12, y + half, x, y + h,
+                x + 28, y + h, x + SIZE, y + half,
+                x + 28, y, x, y),
+            x + SIZE,
+        ),
+        Gates::XOR(_) | Gates::XNOR(_) => (
+            format!("M {} {} Q {} {} {} {} M {} {} Q {} {} {} {} Q {} {} {} {} Q {} {} {} {} Z",
+                x - 6, y,
+                x + 6, y + half, x - 6, y + h,
+                x, y,
+                x + 12, y + half, x, y + h,
+                x + 28, y + h, x + SIZE, y + half,
+                x + 28, y, x, y),
+            x + SIZE,
+        ),
+        Gates::INV(_) | Gates::BUF(_) | Gates::TRIBUF(_) => (
+            format!("M {} {} L {} {} L {} {} Z", x, y + 4, x + 32, y + half, x, y + h - 4),
+            x + 32,
+        ),
+        Gates::CELL(gate) => {
+            let shape = format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" class=\"gate\"/><text x=\"{}\" y=\"{}\" text-anchor=\"middle\" class=\"net\">{}</text>",
+                x, y, SIZE, h, x + half, y + half + 3, escape(&gate.cell.name));
+            return (shape, x + SIZE);
+        },
+    };
+
+    let inverting = matches!(gate, Gates::NAND(_) | Gates::NOR(_) | Gates::XNOR(_) | Gates::INV(_));
+
+    let mut shape = format!("<path d=\"{}\" class=\"gate\"/>", path);
+    if inverting {
+        shape.push_str(&format!("<circle cx=\"{}\" cy=\"{}\" r=\"{}\" class=\"gate\"/>", right + BUBBLE, y + half, BUBBLE));
+        return (shape, right + 2 * BUBBLE);
+    }
+
+    (shape, right)
+}
+
+struct Pin {
+    x: i32,
+    y: i32,
+    column: i32,
+}
+
+// Draws the circuit as an SVG schematic. Gates are placed in columns by logic
+// level with primary inputs (and flop outputs) on the left and primary
+// outputs on the right. Each net leaves its driver horizontally, turns onto
+// its own vertical track in the gap before the column it feeds, and enters
+// the input pin horizontally. Connections that run backwards, into a flop,
+// are routed along lanes under the drawing. With values on, every net is
+// colored and labeled with its simulated value.
+pub fn writesvg<W: Write>(circuit: &Circuit, values: bool, out: &mut W) -> io::Result<()> {
+    let (levels, order) = levelorder(&circuit.gates, &circuit.wires);
+    let last = levels.iter().copied().max().unwrap_or(0) as i32 + 1;
+
+    let mut rows: HashMap<i32,i32> = HashMap::new();
+    let mut place = |column: i32| -> i32 {
+        let row = rows.entry(column).or_insert(0);
+        *row += 1;
+        *row - 1
+    };
+
+    let mut sources: HashMap<u32,Vec<Pin>> = HashMap::new();
+    let mut sinks: Vec<(u32,Pin)> = vec![];
+    let mut body = String::new();
+
+    for net in &circuit.inputs {
+        let y = rowy(place(0)) + SIZE / 2;
+        let x = colx(0) + SIZE / 2;
+        body.push_str(&format!("<circle cx=\"{}\" cy=\"{}\" r=\"6\" class=\"port\"/>", x, y));
+        body.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n", x - 10, y + 4, escape(&circuit.netname(*net))));
+        sources.entry(*net).or_default().push(Pin { x: x + 6, y, column: 0 });
+    }
+
+    for flop in &circuit.flops {
+        let x = colx(0);
+        let y = rowy(place(0));
+        body.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" class=\"gate\"/>", x, y, SIZE, SIZE));
+        body.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">DFF</text>\n", x + SIZE / 2, y + SIZE / 2 + 4));
+        sources.entry(flop.q).or_default().push(Pin { x: x + SIZE, y: y + SIZE / 2, column: 0 });
+        let ins: Vec<u32> = std::iter::once(flop.d).chain(flop.reset).collect();
+        for (k, net) in ins.iter().enumerate() {
+            let py = y + (k as i32 + 1) * SIZE / (ins.len() as i32 + 1);
+            sinks.push((*net, Pin { x, y: py, column: 0 }));
+        }
+    }
+
+    for g in &order {
+        let gate = &circuit.gates.gatestack[*g];
+        let column = levels[*g] as i32;
+        let x = colx(column);
+        let y = rowy(place(column));
+
+        let (shape, right) = symbol(gate, x, y);
+        body.push_str(&format!("<g><title>gate {} {}</title>{}</g>\n", g, gate.name(), shape));
+
+        let ins = gate.net_inputs();
+        for (k, net) in ins.iter().enumerate() {
+            let py = y + (k as i32 + 1) * SIZE / (ins.len() as i32 + 1);
+            sinks.push((*net, Pin { x, y: py, column }));
+        }
+        sources.entry(gate.net_output()).or_default().push(Pin { x: right, y: y + SIZE / 2, column });
+    }
+
+    for net in &circuit.outputs {
+        let x = colx(last);
+        let y = rowy(place(last)) + SIZE / 2;
+        body.push_str(&format!("<circle cx=\"{}\" cy=\"{}\" r=\"6\" class=\"port\"/>", x + 6, y));
+        body.push_str(&format!("<text x=\"{}\" y=\"{}\">{}</text>\n", x + 16, y + 4, escape(&circuit.netname(*net))));
+        sinks.push((*net, Pin { x, y, column: last }));
+    }
+
+    let height = rowy(rows.values().copied().max().unwrap_or(1));
+    let mut tracks: HashMap<(i32,u32),i32> = HashMap::new();
+    let mut used: HashMap<i32,i32> = HashMap::new();
+    let mut lanes: HashMap<u32,i32> = HashMap::new();
+    let mut wires = String::new();
+
+    // every driver of a bus is wired to each of its sinks
+    for (net, sink) in &sinks {
+        for source in sources.get(net).map(|s| &s[..]).unwrap_or(&[]) {
+            let path = if sink.column > source.column {
+                let track = *tracks.entry((sink.column, *net)).or_insert_with(|| {
+                    let next = used.entry(sink.column).or_insert(0);
+                    *next += 1;
+                    (*next - 1) % 12
+                });
+                let mut channel = sink.x - 15 - 6 * track;
+                if channel <= source.x {
+                    channel = (source.x + sink.x) / 2;
+                }
+                format!("M {} {} H {} V {} H {}", source.x, source.y, channel, sink.y, sink.x)
+            } else {
+                let count = lanes.len() as i32;
+                let lane = height + 10 + 8 * *lanes.entry(*net).or_insert(count);
+                format!("M {} {} H {} V {} H {} V {} H {}", source.x, source.y, source.x + 10, lane, sink.x - 15, sink.y, sink.x)
+            };
+
+            let level = circuit.wires.get(net).map(|w| w.level).unwrap_or(FiveLogic::X);
+            let style = match (values, level) {
+                (false, _) => String::new(),
+                (true, FiveLogic::D | FiveLogic::Dnot) => format!(" stroke=\"{}\" stroke-width=\"2.5\"", color(level)),
+                (true, _) => format!(" stroke=\"{}\"", color(level)),
+            };
+
+            wires.push_str(&format!("<path d=\"{}\" class=\"wire\"{}/>\n", path, style));
+        }
+    }
+
+    let mut labels = String::new();
+    // a bus is labeled once, at its first driver
+    let mut labeled: Vec<&u32> = sources.keys().collect();
+    labeled.sort();
+    for net in labeled {
+        let source = &sources[net][0];
+        let level = circuit.wires.get(net).map(|w| w.level).unwrap_or(FiveLogic::X);
+        let (text, style) = if values {
+            (format!("{}={}", escape(&circuit.netname(*net)), levelname(level)), format!(" fill=\"{}\"", color(level)))
+        } else {
+            (escape(&circuit.netname(*net)), String::new())
+        };
+        labels.push_str(&format!("<text x=\"{}\" y=\"{}\" class=\"net\"{}>{}</text>\n", source.x + 3, source.y - 4, style, text));
+    }
+
+    let width = colx(last) + COLUMN;
+    let height = height + 20 + 8 * lanes.len() as i32;
+
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">", width, height, width, height)?;
+    writeln!(out, "<style>")?;
+    writeln!(out, "  text {{ font-family: Helvetica, sans-serif; font-size: 12px; }}")?;
+    writeln!(out, "  .net {{ font-size: 9px; fill: #444; }}")?;
+    writeln!(out, "  .gate {{ fill: white; stroke: black; stroke-width: 1.5; }}")?;
+    writeln!(out, "  .port {{ fill: white; stroke: black; stroke-width: 1.5; }}")?;
+    writeln!(out, "  .wire {{ fill: none; stroke: black; stroke-width: 1; }}")?;
+    writeln!(out, "</style>")?;
+    write!(out, "{}", wires)?;
+    write!(out, "{}", body)?;
+    write!(out, "{}", labels)?;
+    writeln!(out, "</svg>")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::{parselines, simulate};
+
+    const XOR: &str = "INV 1 3\nINV 2 4\nAND 1 4 5\nAND 2 3 6\nOR 5 6 7\nINPUT 1 2 -1\nOUTPUT 7 -1";
+
+    fn render(circuit: &Circuit, values: bool) -> String {
+        let mut text: Vec<u8> = vec![];
+        writesvg(circuit, values, &mut text).unwrap();
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn layout() {
+        let circuit = parselines("test", XOR.lines().map(String::from)).unwrap();
+        let text = render(&circuit, false);
+
+        assert!(text.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"790\" height=\"200\""));
+        assert!(text.trim_end().ends_with("</svg>"));
+        // both INVs in column 1, both ANDs in column 2 and the OR in column 3
+        assert!(text.contains("<g><title>gate 0 INV</title><path d=\"M 190 44 L 222 60 L 190 76 Z\""));
+        assert!(text.contains("<g><title>gate 3 AND</title><path d=\"M 340 110 H 360 A 20 20 0 0 1 360 150 H 340 Z\""));
+        assert!(text.contains("<g><title>gate 4 OR</title>"));
+        assert_eq!(text.matches("class=\"wire\"").count(), 9);
+        // net 4 leaves the second INV and drops into the lower pin of the first AND
+        assert!(text.contains("<path d=\"M 230 130 H "));
+    }
+
+    #[test]
+    fn bus_drivers() {
+        let circuit = parselines("test", "TRIBUF 1 2 5\nTRIBUF 3 4 5\nINPUT 1 2 3 4 -1\nOUTPUT 5 -1".lines().map(String::from)).unwrap();
+        let text = render(&circuit, false);
+
+        // four input wires and one from each TRIBUF to the output port
+        assert_eq!(text.matches("class=\"wire\"").count(), 6);
+        assert_eq!(text.matches(">5</text>").count(), 2);
+    }
+
+    #[test]
+    fn values() {
+        let mut circuit = parselines("test", XOR.lines().map(String::from)).unwrap();
+
+        circuit.wires.get_mut(&1).unwrap().level = FiveLogic::D;
+        circuit.wires.get_mut(&2).unwrap().level = FiveLogic::ONE;
+        simulate(&mut circuit.gates, &mut circuit.wires);
+
+        let text = render(&circuit, true);
+
+        assert!(text.contains(" fill=\"red\">7=d</text>"));
+        assert!(text.contains(" stroke=\"red\" stroke-width=\"2.5\"/>"));
+        assert!(text.contains(" fill=\"blue\">2=1</text>"));
+        assert!(text.contains(" stroke=\"darkgreen\"/>"));
+    }
+
+    #[test]
+    fn flop_feedback() {
+        let circuit = crate::blif::parsebliflines("t.blif", ".model t\n.inputs a\n.outputs y\n.latch y q 0\n.names a q y\n11 1\n.end".lines().map(String::from)).unwrap();
+        let text = render(&circuit, false);
+
+        assert!(text.contains(">DFF</text>"));
+        // the flop input is fed from the AND output along a lane under the drawing
+        assert!(text.contains("V 190 H 25 V 130 H 40\""));
+    }
+}
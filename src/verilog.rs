@@ -0,0 +1,960 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self,Write};
+
+use crate::cells::{parsefunction, rowbit, Cell, Expr};
+use crate::gates::{Circuit, FiveLogic, Gates, NetTable, ParseError, ParseReason, WireType};
+
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+// Splits Verilog source into identifiers, numbers and single character
+// punctuation, dropping // and /* */ comments.
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let chars: Vec<char> = source.chars().collect();
+    let mut i = 0;
+    let mut line = 1;
+    let mut column = 1;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '\n' {
+            line += 1;
+            column = 1;
+            i += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            column += 1;
+            i += 1;
+            continue;
+        }
+        if c == '/' && i + 1 < chars.len() && chars[i + 1] == '/' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && i + 1 < chars.len() && chars[i + 1] == '*' {
+            i += 2;
+            column += 2;
+            while i < chars.len() && !(chars[i] == '*' && i + 1 < chars.len() && chars[i + 1] == '/') {
+                if chars[i] == '\n' {
+                    line += 1;
+                    column = 1;
+                } else {
+                    column += 1;
+                }
+                i += 1;
+            }
+            i += 2;
+            column += 2;
+            continue;
+        }
+
+        let start = i;
+        if c.is_alphanumeric() || c == '_' || c == '$' || c == '\'' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$' || chars[i] == '\'') {
+                i += 1;
+            }
+        } else if c == '\\' {
+            // escaped identifiers run to the next whitespace
+            while i < chars.len() && !chars[i].is_whitespace() {
+                i += 1;
+            }
+        } else {
+            i += 1;
+        }
+
+        tokens.push(Token { text: chars[start..i].iter().collect(), line, column });
+        column += i - start;
+    }
+
+    tokens
+}
+
+struct Parser<'a> {
+    filename: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.text.as_str())
+    }
+
+    fn error(&self, reason: ParseReason) -> ParseError {
+        let token = self.tokens.get(self.pos).or(self.tokens.last());
+
+        ParseError {
+            file: self.filename.to_string(),
+            line: token.map(|t| t.line).unwrap_or(0),
+            column: token.map(|t| t.column).unwrap_or(0),
+            token: token.map(|t| t.text.clone()).unwrap_or_default(),
+            reason,
+        }
+    }
+
+    fn next(&mut self) -> Result<String, ParseError> {
+        match self.tokens.get(self.pos) {
+            Some(t) => {
+                self.pos += 1;
+                Ok(t.text.clone())
+            },
+            None => Err(self.error(ParseReason::MissingOperand)),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), ParseError> {
+        if self.peek() == Some(text) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(ParseReason::Malformed))
+        }
+    }
+
+    fn number(&mut self) -> Result<i64, ParseError> {
+        match self.peek().map(|t| t.parse::<i64>()) {
+            Some(Ok(n)) => {
+                self.pos += 1;
+                Ok(n)
+            },
+            _ => Err(self.error(ParseReason::NonNumericNet)),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(t) if t.starts_with(|c: char| c.is_alphabetic() || c == '_') => self.next(),
+            Some(t) if t.starts_with('\\') => self.next().map(|t| t[1..].to_string()),
+            _ => Err(self.error(ParseReason::Malformed)),
+        }
+    }
+
+    // A net reference: a plain name or a single bit of a bus, `a[3]`.
+    fn signal(&mut self) -> Result<String, ParseError> {
+        let name = self.identifier()?;
+
+        if self.peek() == Some("[") {
+            self.pos += 1;
+            let bit = self.number()?;
+            self.expect("]")?;
+            return Ok(format!("{}[{}]", name, bit));
+        }
+
+        Ok(name)
+    }
+
+    // An optional `[msb:lsb]` range, returned as the list of bit indices.
+    fn range(&mut self) -> Result<Option<Vec<i64>>, ParseError> {
+        if self.peek() != Some("[") {
+            return Ok(None);
+        }
+        self.pos += 1;
+        let msb = self.number()?;
+        self.expect(":")?;
+        let lsb = self.number()?;
+        self.expect("]")?;
+
+        if msb >= lsb {
+            Ok(Some((lsb..=msb).rev().collect()))
+        } else {
+            Ok(Some((msb..=lsb).collect()))
+        }
+    }
+
+    // A module defining a cell the way writeverilog writes them: a port
+    // list, the inputs, one output and an assign of a function of the inputs.
+    // None for a module of any other shape.
+    fn cellmodule(&mut self) -> Option<Cell> {
+        self.expect("module").ok()?;
+        let name = self.identifier().ok()?;
+        self.expect("(").ok()?;
+        while self.peek() != Some(")") {
+            self.next().ok()?;
+        }
+        self.expect(")").ok()?;
+        self.expect(";").ok()?;
+
+        self.expect("input").ok()?;
+        let mut inputs = vec![self.identifier().ok()?];
+        while self.peek() == Some(",") {
+            self.pos += 1;
+            inputs.push(self.identifier().ok()?);
+        }
+        self.expect(";").ok()?;
+
+        self.expect("output").ok()?;
+        let output = self.identifier().ok()?;
+        self.expect(";").ok()?;
+
+        self.expect("assign").ok()?;
+        if self.identifier().ok()? != output {
+            return None;
+        }
+        self.expect("=").ok()?;
+        let mut function = vec![];
+        while self.peek() != Some(";") {
+            function.push(match self.next().ok()?.as_str() {
+                "~" => "!".to_string(),
+                "1'b0" => "0".to_string(),
+                "1'b1" => "1".to_string(),
+                t => t.to_string(),
+            });
+        }
+        self.expect(";").ok()?;
+        self.expect("endmodule").ok()?;
+
+        // kept as a truth table, as a gate defined in any other format is
+        let function = parsefunction(&function.join(" "), &inputs).ok()?;
+        let n = inputs.len();
+        let table = (0..1usize << n).map(|row| {
+            let bits: Vec<bool> = (0..n).map(|k| rowbit(row, k, n)).collect();
+            function.eval(&bits)
+        }).collect();
+
+        Some(Cell { name, inputs, output, function: Expr::Table(table) })
+    }
+}
+
+fn declare(circuit: &mut Circuit, nets: &mut NetTable, direction: &str, name: &str, range: &Option<Vec<i64>>) {
+    let names: Vec<String> = match range {
+        Some(bits) => bits.iter().map(|b| format!("{}[{}]", name, b)).collect(),
+        None => vec![name.to_string()],
+    };
+
+    for n in names {
+        let id = nets.id(&n);
+        match direction {
+            "input" => circuit.addinput(id),
+            "output" => circuit.addoutput(id),
+            _ => {},
+        }
+    }
+}
+
+pub fn parseverilog(filename: &str) -> Result<Circuit, ParseError> {
+    match fs::read_to_string(filename) {
+        Ok(source) => parseverilogsource(filename, &source),
+        Err(_) => Err(ParseError {
+            file: filename.to_string(),
+            line: 0,
+            column: 0,
+            token: String::new(),
+            reason: ParseReason::UnreadableFile,
+        }),
+    }
+}
+
+// Reads the first module of a gate-level Verilog netlist, along with any
+// cells defined by the modules after it. Ports may be
+// declared in the module header or in the body, buses are split into one net
+// per bit (`a[3]`), and the primitives and/nand/or/nor/xor/xnor/not/buf map
+// onto the gate types, bufif1 onto TRIBUF, with `assign a = b;` and `assign a = ~b;` read as
+// BUF and INV and `assign a = 1'b0;` and `assign a = 1'b1;` as tie cells.
+// Library cells are instantiated like modules, with pins connected by name
+// or in library order.
+pub fn parseverilogsource(filename: &str, source: &str) -> Result<Circuit, ParseError> {
+    let mut p = Parser { filename, tokens: tokenize(source), pos: 0 };
+    let mut circuit = Circuit::new();
+    let mut nets = NetTable::new();
+
+    // cells defined after the netlist have to be known before it is read, and
+    // the library's own cells keep their definitions
+    let starts: Vec<usize> = (1..p.tokens.len()).filter(|i| p.tokens[*i].text == "module").collect();
+    for start in starts {
+        p.pos = start;
+        if let Some(cell) = p.cellmodule() {
+            circuit.cells.entry(cell.name.clone()).or_insert(cell);
+        }
+    }
+    p.pos = 0;
+
+    let mut flops: Vec<(u32, u32, Option<u32>)> = vec![];
+    let mut inits: HashMap<u32,FiveLogic> = HashMap::new();
+    let mut clocks: Vec<u32> = vec![];
+
+    p.expect("module")?;
+    p.identifier()?;
+
+    if p.peek() == Some("(") {
+        p.pos += 1;
+        let mut direction = String::new();
+        let mut range = None;
+
+        while p.peek() != Some(")") {
+            match p.peek() {
+                Some("input") | Some("output") | Some("inout") => {
+                    direction = p.next()?;
+                    if direction == "inout" {
+                        p.pos -= 1;
+                        return Err(p.error(ParseReason::UnknownGate));
+                    }
+                    if p.peek() == Some("wire") {
+                        p.pos += 1;
+                    }
+                    range = p.range()?;
+                },
+                Some(",") => p.pos += 1,
+                _ => {
+                    let name = p.identifier()?;
+                    declare(&mut circuit, &mut nets, &direction, &name, &range);
+                },
+            }
+        }
+        p.expect(")")?;
+    }
+    p.expect(";")?;
+
+    loop {
+        let keyword = match p.peek() {
+            Some(k) => k.to_string(),
+            None => return Err(p.error(ParseReason::Malformed)),
+        };
+
+        match keyword.as_str() {
+            "endmodule" => break,
+            "input" | "output" | "wire" | "reg" => {
+                p.pos += 1;
+                let range = p.range()?;
+                loop {
+                    let name = p.identifier()?;
+                    declare(&mut circuit, &mut nets, &keyword, &name, &range);
+                    match p.next()?.as_str() {
+                        "," => continue,
+                        ";" => break,
+                        _ => {
+                            p.pos -= 1;
+                            return Err(p.error(ParseReason::Malformed));
+                        },
+                    }
+                }
+            },
+            "assign" => {
+                p.pos += 1;
+                let out = p.signal()?;
+                p.expect("=")?;
+                if let Some(constant @ ("1'b0" | "1'b1")) = p.peek() {
+                    let tie = if constant == "1'b1" { "TIE1" } else { "TIE0" };
+                    p.pos += 1;
+                    p.expect(";")?;
+                    circuit.addgate(tie, &[], nets.id(&out));
+                    continue;
+                }
+                let invert = p.peek() == Some("~");
+                if invert {
+                    p.pos += 1;
+                }
+                let input = p.signal()?;
+                p.expect(";")?;
+
+                let op = if invert { "INV" } else { "BUF" };
+                circuit.addgate(op, &[nets.id(&input)], nets.id(&out));
+            },
+            "initial" => {
+                p.pos += 1;
+                let q = p.signal()?;
+                p.expect("=")?;
+                let init = match p.next()?.as_str() {
+                    "1'b0" => FiveLogic::ZERO,
+                    "1'b1" => FiveLogic::ONE,
+                    "1'bx" => FiveLogic::X,
+                    _ => {
+                        p.pos -= 1;
+                        return Err(p.error(ParseReason::Unsupported));
+                    },
+                };
+                p.expect(";")?;
+                inits.insert(nets.id(&q), init);
+            },
+            "always" => {
+                // always @(posedge clk) q <= d; or q <= reset ? 1'b0 : d;
+                p.pos += 1;
+                p.expect("@")?;
+                p.expect("(")?;
+                p.expect("posedge")?;
+                let clock = p.signal()?;
+                p.expect(")")?;
+                let q = p.signal()?;
+                p.expect("<")?;
+                p.expect("=")?;
+                let first = p.signal()?;
+                let (d, reset) = if p.peek() == Some("?") {
+                    p.pos += 1;
+                    p.expect("1'b0")?;
+                    p.expect(":")?;
+                    (p.signal()?, Some(first))
+                } else {
+                    (first, None)
+                };
+                p.expect(";")?;
+
+                clocks.push(nets.id(&clock));
+                flops.push((nets.id(&d), nets.id(&q), reset.map(|r| nets.id(&r))));
+            },
+            "and" | "nand" | "or" | "nor" | "xor" | "xnor" | "not" | "buf" | "bufif1" => {
+                p.pos += 1;
+                let op = match keyword.as_str() {
+                    "not" => "INV".to_string(),
+                    "bufif1" => "TRIBUF".to_string(),
+                    k => k.to_ascii_uppercase(),
+                };
+
+                loop {
+                    if p.peek() != Some("(") {
+                        p.identifier()?;
+                    }
+                    p.expect("(")?;
+
+                    let mut pins = vec![p.signal()?];
+                    while p.peek() == Some(",") {
+                        p.pos += 1;
+                        pins.push(p.signal()?);
+                    }
+
+                    let count = circuit.arity(&op).unwrap_or(1) + 1;
+                    if pins.len() < count {
+                        return Err(p.error(ParseReason::MissingOperand));
+                    }
+                    if circuit.arity(&op).is_some() && pins.len() > count {
+                        p.pos -= 1;
+                        return Err(p.error(ParseReason::WrongOperandCount));
+                    }
+                    p.expect(")")?;
+
+                    let ids: Vec<u32> = pins.iter().map(|n| nets.id(n)).collect();
+                    circuit.addwidegate(&op, &ids[1..], ids[0]);
+
+                    match p.next()?.as_str() {
+                        "," => continue,
+                        ";" => break,
+                        _ => {
+                            p.pos -= 1;
+                            return Err(p.error(ParseReason::Malformed));
+                        },
+                    }
+                }
+            },
+            k if circuit.cells.contains_key(k) => {
+                p.pos += 1;
+                let cell = circuit.cells[k].clone();
+                let count = cell.inputs.len();
+
+                if p.peek() != Some("(") {
+                    p.identifier()?;
+                }
+                p.expect("(")?;
+
+                // pins connect by name, .A(a), or by position in library
+                // order with the output last
+                let mut pins: Vec<Option<String>> = vec![None; count + 1];
+                let mut position = 0;
+                while p.peek() != Some(")") {
+                    if position > 0 {
+                        p.expect(",")?;
+                    }
+
+                    if p.peek() == Some(".") {
+                        p.pos += 1;
+                        let pin = p.identifier()?;
+                        let slot = match cell.inputs.iter().position(|i| *i == pin) {
+                            Some(slot) => slot,
+                            None if pin == cell.output => count,
+                            None => {
+                                p.pos -= 1;
+                                return Err(p.error(ParseReason::Malformed));
+                            },
+                        };
+                        p.expect("(")?;
+                        pins[slot] = Some(p.signal()?);
+                        p.expect(")")?;
+                    } else {
+                        if position > count {
+                            return Err(p.error(ParseReason::WrongOperandCount));
+                        }
+                        pins[position] = Some(p.signal()?);
+                    }
+                    position += 1;
+                }
+
+                if pins.iter().any(|pin| pin.is_none()) {
+                    return Err(p.error(ParseReason::MissingOperand));
+                }
+                p.expect(")")?;
+                p.expect(";")?;
+
+                let ids: Vec<u32> = pins.iter().flatten().map(|n| nets.id(n)).collect();
+                circuit.addgate(k, &ids[..count], ids[count]);
+            },
+            _ => return Err(p.error(ParseReason::UnknownGate)),
+        }
+    }
+
+    for (d, q, reset) in flops {
+        let init = inits.get(&q).copied().unwrap_or(FiveLogic::X);
+        match reset {
+            Some(reset) => circuit.addresetflop(d, q, init, reset),
+            None => circuit.addflop(d, q, init),
+        }
+    }
+
+    // all flops share the one clock, so a clock used for nothing else is not
+    // an input of the logic
+    for clock in clocks {
+        let used = circuit.wires.get(&clock).is_some_and(|w| !w.fanout.is_empty())
+            || circuit.outputs.contains(&clock)
+            || circuit.flops.iter().any(|f| f.d == clock || f.reset == Some(clock));
+        if !used {
+            circuit.inputs.retain(|n| *n != clock);
+            if let Some(wire) = circuit.wires.get_mut(&clock) {
+                wire.wiretype = WireType::Net;
+            }
+        }
+    }
+
+    circuit.names = nets.names();
+
+    Ok(circuit)
+}
+
+// Verilog's reserved words, which a net name has to be escaped to use.
+const RESERVED: [&str; 124] = [
+    "always", "and", "assign", "automatic", "begin", "buf", "bufif0", "bufif1", "case", "casex", "casez",
+    "cell", "cmos", "config", "deassign", "default", "defparam", "design", "disable", "edge", "else", "end",
+    "endcase", "endconfig", "endfunction", "endgenerate", "endmodule", "endprimitive", "endspecify",
+    "endtable", "endtask", "event", "for", "force", "forever", "fork", "function", "generate", "genvar",
+    "highz0", "highz1", "if", "ifnone", "incdir", "include", "initial", "inout", "input", "instance",
+    "integer", "join", "large", "liblist", "library", "localparam", "macromodule", "medium", "module",
+    "nand", "negedge", "nmos", "nor", "noshowcancelled", "not", "notif0", "notif1", "or", "output",
+    "parameter", "pmos", "posedge", "primitive", "pull0", "pull1", "pulldown", "pullup",
+    "pulsestyle_onevent", "pulsestyle_ondetect", "rcmos", "real", "realtime", "reg", "release", "repeat",
+    "rnmos", "rpmos", "rtran", "rtranif0", "rtranif1", "scalared", "showcancelled", "signed", "small",
+    "specify", "specparam", "strong0", "strong1", "supply0", "supply1", "table", "task", "time", "tran",
+    "tranif0", "tranif1", "tri", "tri0", "tri1", "triand", "trior", "trireg", "unsigned", "use", "uwire",
+    "vectored", "wait", "wand", "weak0", "weak1", "while", "wire", "wor", "xnor", "xor",
+];
+
+// A net's name as a Verilog identifier. Names that are not plain identifiers,
+// such as bus bits, and reserved words are escaped. Nets without a name become
+// n<number>, with '_' appended while that is some named net's name.
+fn netname(circuit: &Circuit, net: u32) -> String {
+    match circuit.names.get(&net) {
+        Some(name) if name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+            && !RESERVED.contains(&name.as_str()) => name.clone(),
+        Some(name) => format!("\\{} ", name),
+        None => {
+            let mut name = format!("n{}", net);
+            while circuit.names.values().any(|n| *n == name) {
+                name.push('_');
+            }
+            name
+        },
+    }
+}
+
+// A cell function as a Verilog expression, in parentheses where it binds
+// more loosely than `level`: 0 for |, 1 for ^, 2 for & and 3 for an operand.
+// A truth table is written as the sum of its on-set rows.
+fn expression(function: &Expr, pins: &[String], level: u8) -> String {
+    let terms = |terms: &[Expr], op: &str, level: u8| -> String {
+        terms.iter().map(|t| expression(t, pins, level)).collect::<Vec<String>>().join(op)
+    };
+
+    let (text, own) = match function {
+        Expr::Pin(k) => (pins[*k].clone(), 3),
+        Expr::Const(value) => (if *value { "1'b1" } else { "1'b0" }.to_string(), 3),
+        Expr::Not(e) => (format!("~{}", expression(e, pins, 3)), 3),
+        Expr::And(t) => (terms(t, " & ", 3), 2),
+        Expr::Xor(t) => (terms(t, " ^ ", 2), 1),
+        Expr::Or(t) => (terms(t, " | ", 1), 0),
+        Expr::Table(table) => {
+            let n = pins.len();
+            let rows: Vec<Expr> = (0..table.len()).filter(|row| table[*row])
+                .map(|row| Expr::And((0..n).map(|k| match rowbit(row, k, n) {
+                    true => Expr::Pin(k),
+                    false => Expr::Not(Box::new(Expr::Pin(k))),
+                }).collect()))
+                .collect();
+
+            let sum = match rows.len() {
+                0 => Expr::Const(false),
+                r if r == table.len() => Expr::Const(true),
+                _ => Expr::Or(rows),
+            };
+            return expression(&sum, pins, level);
+        },
+    };
+
+    if own < level { format!("({})", text) } else { text }
+}
+
+// A module for a cell, so the netlist can be elaborated without the library.
+fn writecell<W: Write>(cell: &Cell, out: &mut W) -> io::Result<()> {
+    let mut ports = cell.inputs.clone();
+    ports.push(cell.output.clone());
+
+    writeln!(out)?;
+    writeln!(out, "module {} ({});", cell.name, ports.join(", "))?;
+    writeln!(out, "  input {};", cell.inputs.join(", "))?;
+    writeln!(out, "  output {};", cell.output)?;
+    writeln!(out, "  assign {} = {};", cell.output, expression(&cell.function, &cell.inputs, 0))?;
+    writeln!(out, "endmodule")
+}
+
+// Writes the circuit as a structural Verilog module with one primitive
+// instance per gate. Cells are instantiated with their pins connected by name
+// and defined in modules of their own after the netlist, apart from tie cells,
+// which become constant assigns. Flops are always blocks on a clock port
+// added ahead of the inputs, with their initial values as initial statements.
+pub fn writeverilog<W: Write>(circuit: &Circuit, module: &str, out: &mut W) -> io::Result<()> {
+    let mut clock = "clk".to_string();
+    while circuit.names.values().any(|n| *n == clock) {
+        clock.push('_');
+    }
+    let clock = if circuit.flops.is_empty() { None } else { Some(clock) };
+
+    let ports: Vec<String> = clock.iter().cloned()
+        .chain(circuit.inputs.iter()
+            .chain(circuit.outputs.iter().filter(|o| !circuit.inputs.contains(o)))
+            .map(|n| netname(circuit, *n)))
+        .collect();
+
+    writeln!(out, "module {} ({});", module, ports.join(", "))?;
+
+    if let Some(clock) = &clock {
+        writeln!(out, "  input {};", clock)?;
+    }
+    for net in &circuit.inputs {
+        writeln!(out, "  input {};", netname(circuit, *net))?;
+    }
+    for net in circuit.outputs.iter().filter(|o| !circuit.inputs.contains(o)) {
+        writeln!(out, "  output {};", netname(circuit, *net))?;
+    }
+
+    let registers: Vec<u32> = circuit.flops.iter().map(|f| f.q).collect();
+    let mut internal: Vec<u32> = circuit.wires.keys()
+        .filter(|n| !circuit.inputs.contains(n) && !circuit.outputs.contains(n) && !registers.contains(n))
+        .copied()
+        .collect();
+    internal.sort();
+
+    if !internal.is_empty() || !registers.is_empty() {
+        writeln!(out)?;
+    }
+    for net in &internal {
+        writeln!(out, "  wire {};", netname(circuit, *net))?;
+    }
+    for net in &registers {
+        writeln!(out, "  reg {};", netname(circuit, *net))?;
+    }
+    writeln!(out)?;
+
+    let mut cells: Vec<&Cell> = vec![];
+
+    for (i, gate) in circuit.gates.gatestack.iter().enumerate() {
+        if let Gates::CELL(cell) = gate {
+            if cell.cell.inputs.is_empty() {
+                writeln!(out, "  assign {} = {};", netname(circuit, cell.net_out), expression(&cell.cell.function, &[], 0))?;
+                continue;
+            }
+            if !cells.iter().any(|c| c.name == cell.cell.name) {
+                cells.push(&cell.cell);
+            }
+
+            let mut pins: Vec<String> = cell.cell.inputs.iter().zip(&cell.net_in)
+                .map(|(pin, net)| format!(".{}({})", pin, netname(circuit, *net)))
+                .collect();
+            pins.push(format!(".{}({})", cell.cell.output, netname(circuit, cell.net_out)));
+
+            writeln!(out, "  {} g{} ({});", cell.cell.name, i, pins.join(", "))?;
+            continue;
+        }
+
+        let primitive = match gate {
+            Gates::INV(_) => "not".to_string(),
+            Gates::TRIBUF(_) => "bufif1".to_string(),
+            _ => gate.name().to_ascii_lowercase(),
+        };
+
+        let mut pins = vec![netname(circuit, gate.net_output())];
+        pins.extend(gate.net_inputs().iter().map(|n| netname(circuit, *n)));
+
+        writeln!(out, "  {} g{} ({});", primitive, i, pins.join(", "))?;
+    }
+
+    if let Some(clock) = &clock {
+        writeln!(out)?;
+        for flop in &circuit.flops {
+            let (d, q) = (netname(circuit, flop.d), netname(circuit, flop.q));
+            match flop.init {
+                FiveLogic::ZERO => writeln!(out, "  initial {} = 1'b0;", q)?,
+                FiveLogic::ONE => writeln!(out, "  initial {} = 1'b1;", q)?,
+                _ => {},
+            }
+            match flop.reset {
+                Some(reset) => writeln!(out, "  always @(posedge {}) {} <= {} ? 1'b0 : {};", clock, q, netname(circuit, reset), d)?,
+                None => writeln!(out, "  always @(posedge {}) {} <= {};", clock, q, d)?,
+            }
+        }
+    }
+
+    writeln!(out, "endmodule")?;
+
+    for cell in cells {
+        writecell(cell, out)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::{parselines, simulate};
+
+    fn run(circuit: &mut Circuit, values: &[FiveLogic]) -> Vec<FiveLogic> {
+        for (net, value) in circuit.inputs.iter().zip(values) {
+            circuit.wires.get_mut(net).unwrap().level = *value;
+        }
+        simulate(&mut circuit.gates, &mut circuit.wires);
+        circuit.outputs.iter().map(|o| circuit.wires[o].level).collect()
+    }
+
+    #[test]
+    fn xor_module() {
+        let source = "// two input xor
+module xor2 (a, b, y);
+  input a, b;
+  output y;
+  wire an, bn, t1, t2;
+
+  not u1 (an, a), u2 (bn, b);
+  and (t1, a, bn);
+  and /* second */ (t2, b, an);
+  or u5 (y, t1, t2);
+endmodule
+";
+        let mut circuit = parseverilogsource("xor2.v", source).unwrap();
+
+        assert_eq!(circuit.gates.gatestack.len(), 5);
+        assert_eq!(circuit.inputs.len(), 2);
+
+        let one = FiveLogic::ONE;
+        let zero = FiveLogic::ZERO;
+        assert_eq!(run(&mut circuit, &[one, zero]), vec![one]);
+        assert_eq!(run(&mut circuit, &[one, one]), vec![zero]);
+    }
+
+    #[test]
+    fn ansi_ports_and_buses() {
+        let source = "module top (input [2:0] a, input c, output y, output z);
+  nand g1 (y, a[0], a[1], a[2]);
+  assign z = ~c;
+endmodule";
+        let mut circuit = parseverilogsource("top.v", source).unwrap();
+
+        assert_eq!(circuit.inputs.len(), 4);
+        assert_eq!(circuit.outputs.len(), 2);
+
+        let one = FiveLogic::ONE;
+        let zero = FiveLogic::ZERO;
+        assert_eq!(run(&mut circuit, &[one, one, one, zero]), vec![zero, one]);
+        assert_eq!(run(&mut circuit, &[one, zero, one, one]), vec![one, zero]);
+    }
+
+    #[test]
+    fn write_and_reread() {
+        let source = "module top (input [2:0] a, input c, output y, output z);
+  nand g1 (y, a[0], a[1], a[2]);
+  assign z = ~c;
+endmodule";
+        let mut circuit = parseverilogsource("top.v", source).unwrap();
+
+        let mut text: Vec<u8> = vec![];
+        writeverilog(&circuit, "top", &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+
+        assert!(text.starts_with("module top (\\a[2] , \\a[1] , \\a[0] , c, y, z);\n  input \\a[2] ;\n"));
+        assert!(!text.contains("wire"));
+        assert!(text.contains("  nand g0 (y, \\a[0] , \\a[1] , \\a[2] );\n"));
+        assert!(text.contains("  not g1 (z, c);\n"));
+
+        let mut reread = parseverilogsource("top.v", &text).unwrap();
+        assert_eq!(reread.netname(reread.inputs[2]), "a[0]");
+
+        let values = [FiveLogic::ZERO, FiveLogic::ONE];
+        for v in 0..16 {
+            let vector: Vec<FiveLogic> = (0..4).map(|b| values[(v >> b) & 1]).collect();
+            assert_eq!(run(&mut reread, &vector), run(&mut circuit, &vector));
+        }
+    }
+
+    #[test]
+    fn numbered_nets() {
+        let mut circuit = parselines("test", "INV 3 y\nINV n3 wire\nINPUT 3 n3 -1\nOUTPUT y wire -1".lines().map(String::from)).unwrap();
+
+        let mut text: Vec<u8> = vec![];
+        writeverilog(&circuit, "top", &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+
+        assert!(text.starts_with("module top (n3_, n3, y, \\wire );\n"));
+
+        let mut reread = parseverilogsource("top.v", &text).unwrap();
+        assert_eq!(reread.inputs.len(), 2);
+        let values = [FiveLogic::ZERO, FiveLogic::ONE];
+        for v in 0..4 {
+            let vector: Vec<FiveLogic> = (0..2).map(|b| values[(v >> b) & 1]).collect();
+            assert_eq!(run(&mut reread, &vector), run(&mut circuit, &vector));
+        }
+    }
+
+    #[test]
+    fn cell_instances() {
+        let source = "module top (a, b, s, c, y, z);
+  input a, b, s, c;
+  output y, z;
+  MUX2 m1 (.S(s), .A(a), .B(b), .Y(y));
+  AOI21 u2 (a, b, c, z);
+endmodule";
+        let mut circuit = parseverilogsource("top.v", source).unwrap();
+
+        assert_eq!(circuit.gates.gatestack[0].net_inputs(), vec![circuit.inputs[0], circuit.inputs[1], circuit.inputs[2]]);
+        assert_eq!(circuit.gates.gatestack[1].net_inputs(), vec![circuit.inputs[0], circuit.inputs[1], circuit.inputs[3]]);
+
+        let (zero, one) = (FiveLogic::ZERO, FiveLogic::ONE);
+        assert_eq!(run(&mut circuit, &[zero, one, one, zero]), vec![one, one]);
+        assert_eq!(run(&mut circuit, &[one, one, zero, zero]), vec![one, zero]);
+
+        let mut text: Vec<u8> = vec![];
+        writeverilog(&circuit, "top", &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("  MUX2 g0 (.A(a), .B(b), .S(s), .Y(y));\n"));
+        assert!(text.contains("  AOI21 g1 (.A1(a), .A2(b), .B(c), .Y(z));\n"));
+
+        // the cells are defined after the netlist
+        assert!(text.ends_with("endmodule\n\nmodule MUX2 (A, B, S, Y);\n  input A, B, S;\n  output Y;\n  assign Y = ~S & A | S & B;\nendmodule\n\nmodule AOI21 (A1, A2, B, Y);\n  input A1, A2, B;\n  output Y;\n  assign Y = ~(A1 & A2 | B);\nendmodule\n"));
+    }
+
+    #[test]
+    fn tie_cells() {
+        let source = "module top (a, y, z);\n  input a;\n  output y, z;\n  wire one;\n  assign one = 1'b1;\n  and g1 (y, a, one);\n  assign z = 1'b0;\nendmodule";
+        let mut circuit = parseverilogsource("top.v", source).unwrap();
+
+        let names: Vec<&str> = circuit.gates.gatestack.iter().map(|g| g.name()).collect();
+        assert_eq!(names, vec!["TIE1", "AND", "TIE0"]);
+        assert_eq!(run(&mut circuit, &[FiveLogic::D]), vec![FiveLogic::D, FiveLogic::ZERO]);
+
+        let mut text: Vec<u8> = vec![];
+        writeverilog(&circuit, "top", &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("  assign one = 1'b1;\n  and g1 (y, a, one);\n  assign z = 1'b0;\nendmodule\n"));
+        assert!(!text.contains("TIE"));
+    }
+
+    #[test]
+    fn defined_gates() {
+        let mut circuit = crate::gates::parselines("test", "DEFGATE MAJ3 3 00010111\nMAJ3 a b c m\nMUX2 m a b y\nINPUT a b c -1\nOUTPUT y -1".lines().map(String::from)).unwrap();
+
+        let mut text: Vec<u8> = vec![];
+        writeverilog(&circuit, "top", &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("\nmodule MAJ3 (A, B, C, Y);\n  input A, B, C;\n  output Y;\n  assign Y = ~A & B & C | A & ~B & C | A & B & ~C | A & B & C;\nendmodule\n"));
+
+        let mut reread = parseverilogsource("top.v", &text).unwrap();
+        assert_eq!(reread.cells["MAJ3"], circuit.cells["MAJ3"]);
+        assert_eq!(reread.cells["MUX2"], circuit.cells["MUX2"]);
+
+        let values = [FiveLogic::ZERO, FiveLogic::ONE];
+        for v in 0..8 {
+            let vector: Vec<FiveLogic> = (0..3).map(|b| values[(v >> b) & 1]).collect();
+            assert_eq!(run(&mut reread, &vector), run(&mut circuit, &vector));
+        }
+    }
+
+    #[test]
+    fn flops() {
+        let mut circuit = crate::gates::parselines("test", "DFF a q1 r\nDFF q1 q2\nAND q1 q2 y\nINPUT a r -1\nOUTPUT y q2 -1".lines().map(String::from)).unwrap();
+        circuit.flops[1].init = FiveLogic::ONE;
+        circuit.names.insert(1000, "clk".to_string());
+
+        let mut text: Vec<u8> = vec![];
+        writeverilog(&circuit, "top", &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.starts_with("module top (clk_, a, r, y, q2);\n  input clk_;\n"));
+        assert!(text.contains("  reg q1;\n  reg q2;\n"));
+        assert!(text.ends_with("  always @(posedge clk_) q1 <= r ? 1'b0 : a;\n  initial q2 = 1'b1;\n  always @(posedge clk_) q2 <= q1;\nendmodule\n"));
+
+        let mut reread = parseverilogsource("top.v", &text).unwrap();
+        let names = |c: &Circuit, nets: &[u32]| nets.iter().map(|n| c.netname(*n)).collect::<Vec<String>>();
+        assert_eq!(names(&reread, &reread.inputs), vec!["a", "r"]);
+        let flops = |c: &Circuit| c.flops.iter().map(|f| (c.netname(f.d), c.netname(f.q), f.reset.map(|r| c.netname(r)), f.init)).collect::<Vec<_>>();
+        assert_eq!(flops(&reread), flops(&circuit));
+
+        let run = |circuit: &mut Circuit| {
+            let mut state: Vec<FiveLogic> = crate::logic::powerup(circuit);
+            let mut seen = vec![];
+            for vector in [[1, 1], [1, 0], [0, 0], [1, 0]] {
+                let inputs: Vec<FiveLogic> = vector.iter().map(|v| crate::gates::tologic(Some(v))).collect();
+                let (nets, next) = crate::logic::clock(circuit, &state, &inputs);
+                state = next;
+                seen.push(circuit.outputs.iter().map(|o| nets[o]).collect::<Vec<FiveLogic>>());
+            }
+            seen
+        };
+        assert_eq!(run(&mut reread), run(&mut circuit));
+    }
+
+    #[test]
+    fn table_expression() {
+        let pins: Vec<String> = ["A", "B", "C"].iter().map(|p| p.to_string()).collect();
+        let table = |bits: &str| Expr::Table(bits.chars().map(|c| c == '1').collect());
+
+        assert_eq!(expression(&table("00010111"), &pins, 0), "~A & B & C | A & ~B & C | A & B & ~C | A & B & C");
+        assert_eq!(expression(&table("00000000"), &pins, 0), "1'b0");
+        assert_eq!(expression(&Expr::Not(Box::new(table("10000000"))), &pins, 0), "~(~A & ~B & ~C)");
+        assert_eq!(expression(&Expr::And(vec![Expr::Xor(vec![Expr::Pin(0), Expr::Pin(1)]), Expr::Pin(2)]), &pins, 0), "(A ^ B) & C");
+    }
+
+    #[test]
+    fn tristate_bus() {
+        let source = "module top (a, b, s, y);
+  input a, b, s;
+  output y;
+  wire ns;
+  not g0 (ns, s);
+  bufif1 g1 (y, a, ns), g2 (y, b, s);
+endmodule";
+        let mut circuit = parseverilogsource("top.v", source).unwrap();
+
+        let (zero, one) = (FiveLogic::ZERO, FiveLogic::ONE);
+        assert_eq!(run(&mut circuit, &[one, zero, zero]), vec![one]);
+        assert_eq!(run(&mut circuit, &[one, zero, one]), vec![zero]);
+
+        let mut text: Vec<u8> = vec![];
+        writeverilog(&circuit, "top", &mut text).unwrap();
+        assert!(String::from_utf8(text).unwrap().contains("  bufif1 g2 (y, b, s);\n"));
+
+        let e = parseverilogsource("top.v", "module m (a, y);\n  input a;\n  output y;\n  bufif1 g (y, a);\nendmodule").err().unwrap();
+        assert_eq!(e.reason, ParseReason::MissingOperand);
+    }
+
+    #[test]
+    fn errors() {
+        let e = parseverilogsource("bad.v", "module m (a, y);\n  input a;\n  output y;\n  dff r1 (y, a);\nendmodule").err().unwrap();
+        assert_eq!((e.line, e.column, e.token.as_str(), e.reason), (4, 3, "dff", ParseReason::UnknownGate));
+
+        let e = parseverilogsource("bad.v", "module m (a, y);\n  not (y);\nendmodule").err().unwrap();
+        assert_eq!((e.line, e.reason), (2, ParseReason::MissingOperand));
+
+        let e = parseverilogsource("bad.v", "module m (a, y);\n  not (y, a, a);\nendmodule").err().unwrap();
+        assert_eq!((e.line, e.column, e.reason), (2, 14, ParseReason::WrongOperandCount));
+    }
+}